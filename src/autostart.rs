@@ -0,0 +1,170 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// launchd label of the login item on macOS, after the D-Bus name on Linux
+const LAUNCH_AGENT_LABEL: &str = "io.github.DeskControl";
+
+/// Whether the tray app starts at login, and from which entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    pub enabled: bool,
+    pub path: PathBuf,
+    /// Whether the entry starts this executable, rather than one since moved or replaced
+    pub current: bool,
+}
+
+/// The login item: an XDG autostart entry, or a launch agent on macOS
+fn entry_path() -> Result<PathBuf> {
+    if cfg!(target_os = "macos") {
+        let home = dirs::home_dir().context("Could not find home directory")?;
+        Ok(home
+            .join("Library")
+            .join("LaunchAgents")
+            .join(format!("{}.plist", LAUNCH_AGENT_LABEL)))
+    } else {
+        let config = dirs::config_dir().context("Could not find the user config directory")?;
+        Ok(config.join("autostart").join("desk-control.desktop"))
+    }
+}
+
+fn entry(exe: &Path) -> String {
+    if cfg!(target_os = "macos") {
+        launch_agent(exe)
+    } else {
+        desktop_entry(exe)
+    }
+}
+
+/// XDG autostart entry starting the tray app
+fn desktop_entry(exe: &Path) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Desk Control\n\
+         Comment=Standing desk presets in the system tray\n\
+         Exec={}\n\
+         Terminal=false\n\
+         X-GNOME-Autostart-enabled=true\n",
+        desktop_exec_arg(&exe.display().to_string())
+    )
+}
+
+/// Quote an `Exec=` argument as the Desktop Entry spec asks
+fn desktop_exec_arg(arg: &str) -> String {
+    // A lone % starts a field code like %f, quoted or not
+    let arg = &arg.replace('%', "%%");
+    let reserved = |c: char| c.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(c);
+    if !arg.contains(reserved) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            // Escaped once for the quoting and once more for the key file
+            '\\' => quoted.push_str("\\\\\\\\"),
+            '"' | '`' | '$' => {
+                quoted.push_str("\\\\");
+                quoted.push(c);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// launchd agent starting the tray app when the user logs in
+fn launch_agent(exe: &Path) -> String {
+    let exe = exe
+        .display()
+        .to_string()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n\
+         <dict>\n\
+         \t<key>Label</key>\n\
+         \t<string>{LAUNCH_AGENT_LABEL}</string>\n\
+         \t<key>ProgramArguments</key>\n\
+         \t<array>\n\
+         \t\t<string>{exe}</string>\n\
+         \t</array>\n\
+         \t<key>RunAtLoad</key>\n\
+         \t<true/>\n\
+         \t<key>ProcessType</key>\n\
+         \t<string>Interactive</string>\n\
+         </dict>\n\
+         </plist>\n"
+    )
+}
+
+/// Start the tray app at login, returning the entry's path
+pub fn enable() -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Could not find the desk-control executable")?;
+    let path = entry_path()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(&path, entry(&exe)).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Stop starting at login, returning the removed entry's path if there was one
+pub fn disable() -> Result<Option<PathBuf>> {
+    let path = entry_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    Ok(Some(path))
+}
+
+pub fn status() -> Result<Status> {
+    let path = entry_path()?;
+    let Ok(contents) = std::fs::read_to_string(&path) else {
+        return Ok(Status {
+            enabled: false,
+            path,
+            current: false,
+        });
+    };
+    let current = match std::env::current_exe() {
+        Ok(exe) => contents == entry(&exe),
+        Err(_) => false,
+    };
+    Ok(Status {
+        enabled: true,
+        path,
+        current,
+    })
+}
+
+/// Whether the tray app starts at login, for its checkbox
+pub fn is_enabled() -> bool {
+    status().map(|status| status.enabled).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries_start_the_tray_app() {
+        let desktop = desktop_entry(Path::new("/usr/bin/desk-control"));
+        assert!(desktop.starts_with("[Desktop Entry]\n"));
+        assert!(desktop.contains("\nExec=/usr/bin/desk-control\n"));
+
+        let quoted = desktop_entry(Path::new("/home/alex/My Apps/desk-control"));
+        assert!(quoted.contains("\nExec=\"/home/alex/My Apps/desk-control\"\n"));
+        assert_eq!(desktop_exec_arg("/opt/$x"), "\"/opt/\\\\$x\"");
+        assert_eq!(desktop_exec_arg("/opt/100%/desk-control"), "/opt/100%%/desk-control");
+
+        let agent = launch_agent(Path::new("/Applications/Desk & Co/desk-control"));
+        assert!(agent.contains("<string>io.github.DeskControl</string>"));
+        assert!(agent.contains("<string>/Applications/Desk &amp; Co/desk-control</string>"));
+        assert!(agent.contains("<key>RunAtLoad</key>\n\t<true/>"));
+    }
+}
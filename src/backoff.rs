@@ -0,0 +1,59 @@
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Delays between reconnection attempts to the desk
+pub const DESK: (Duration, Duration) = (Duration::from_secs(2), Duration::from_secs(30));
+
+/// Delays between reconnection attempts to the MQTT broker
+pub const BROKER: (Duration, Duration) = (Duration::from_secs(2), Duration::from_secs(60));
+
+/// Growing delay between reconnection attempts, doubling up to a maximum
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    /// Start at the shortest of `(min, max)`
+    pub fn new((min, max): (Duration, Duration)) -> Self {
+        Self { min, max, next: min }
+    }
+
+    /// How long the next `wait` sleeps
+    pub fn delay(&self) -> Duration {
+        self.next
+    }
+
+    /// Go back to the shortest delay, e.g. once connected
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+
+    /// Sleep for the current delay and double it for next time
+    ///
+    /// The delay grows even if the sleep is cut short, e.g. by a `select!`.
+    pub async fn wait(&mut self) {
+        let delay = self.next;
+        self.next = (delay * 2).min(self.max);
+        sleep(delay).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_doubles_up_to_the_maximum_and_resets() {
+        let mut backoff = Backoff::new((Duration::from_millis(2), Duration::from_millis(5)));
+        assert_eq!(backoff.delay(), Duration::from_millis(2));
+        backoff.wait().await;
+        assert_eq!(backoff.delay(), Duration::from_millis(4));
+        backoff.wait().await;
+        assert_eq!(backoff.delay(), Duration::from_millis(5));
+        backoff.reset();
+        assert_eq!(backoff.delay(), Duration::from_millis(2));
+    }
+}
@@ -0,0 +1,431 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+
+use crate::config::{Config, DrinkSize};
+use crate::daemon;
+use crate::desk::watch::WatchEvent;
+use crate::desk::Height;
+use crate::metrics::MoveSource;
+
+/// Wait before looking for a daemon again after losing the last one
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Status bar whose module format `desk-control bar` speaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BarFormat {
+    /// Custom module JSON (`return-type: json`), one object per line
+    Waybar,
+    /// Plain text with clickable action tags
+    Polybar,
+    /// Persistent JSON block; clicks arrive on stdin
+    I3blocks,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+}
+
+impl Button {
+    /// X11 button number, as Polybar and i3blocks count them
+    fn number(self) -> u8 {
+        match self {
+            Self::Left => 1,
+            Self::Middle => 2,
+            Self::Right => 3,
+        }
+    }
+
+    fn from_number(number: u64) -> Option<Self> {
+        [Self::Left, Self::Middle, Self::Right]
+            .into_iter()
+            .find(|button| u64::from(button.number()) == number)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Left => "Left",
+            Self::Middle => "Middle",
+            Self::Right => "Right",
+        }
+    }
+}
+
+/// Presets bound to mouse buttons on the module
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Clicks {
+    pub left: Option<DrinkSize>,
+    pub middle: Option<DrinkSize>,
+    pub right: Option<DrinkSize>,
+}
+
+impl Clicks {
+    pub fn get(&self, button: Button) -> Option<DrinkSize> {
+        match button {
+            Button::Left => self.left,
+            Button::Middle => self.middle,
+            Button::Right => self.right,
+        }
+    }
+
+    fn bound(&self) -> impl Iterator<Item = (Button, DrinkSize)> + '_ {
+        [Button::Left, Button::Middle, Button::Right]
+            .into_iter()
+            .filter_map(|button| Some((button, self.get(button)?)))
+    }
+}
+
+/// What the bar knows about the desk, built up from its events
+#[derive(Debug, Default)]
+struct DeskState {
+    /// Configured desk, or `None` if there is none to follow
+    address: Option<String>,
+    connected: bool,
+    height: Option<Height>,
+    speed: i16,
+    moving: bool,
+    target: Option<Height>,
+    error: Option<String>,
+}
+
+impl DeskState {
+    fn apply(&mut self, event: WatchEvent) {
+        match event {
+            WatchEvent::Connecting { .. } => {}
+            WatchEvent::Connected { .. } => {
+                self.connected = true;
+                self.error = None;
+            }
+            WatchEvent::Height { height_mm, speed, moving } => {
+                self.height = Some(height_mm);
+                self.speed = speed;
+                self.moving = moving;
+            }
+            WatchEvent::Disconnected { error, .. } => {
+                self.connected = false;
+                self.moving = false;
+                self.target = None;
+                self.error = Some(error);
+            }
+            WatchEvent::MoveStarted { target_mm, .. } => self.target = Some(target_mm),
+            WatchEvent::MoveFinished { .. } => self.target = None,
+            WatchEvent::MoveFailed { error, .. } => {
+                self.target = None;
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+/// One update of the module, before it is put in a bar's format
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct Block {
+    text: String,
+    tooltip: String,
+    /// `sitting`, `standing`, `moving`, `connected` (no height yet) or
+    /// `disconnected`, for styling
+    class: &'static str,
+}
+
+fn render(config: &Config, clicks: &Clicks, state: &DeskState) -> Block {
+    let mut tooltip = Vec::new();
+
+    let (text, class) = match (state.connected, state.height) {
+        (true, Some(height)) => {
+            let current = config.format_height(height);
+            if state.moving || state.target.is_some() {
+                let arrow = match state.speed.signum() {
+                    1 => "↑ ",
+                    -1 => "↓ ",
+                    _ => "",
+                };
+                match state.target {
+                    Some(target) => {
+                        tooltip.push(format!("Moving to {}, now at {}", config.format_height(target), current))
+                    }
+                    None => tooltip.push(format!("Moving, now at {}", current)),
+                }
+                (format!("{}{}", arrow, current), "moving")
+            } else {
+                let posture = config.profile().presets.posture(height);
+                let mut posture_name = posture.name().to_string();
+                posture_name[..1].make_ascii_uppercase();
+                tooltip.push(format!("{} at {}", posture_name, current));
+                (current, posture.name())
+            }
+        }
+        (true, None) => {
+            tooltip.push("Connected, waiting for the height".to_string());
+            ("…".to_string(), "connected")
+        }
+        (false, _) => {
+            match (&state.address, &state.error) {
+                (None, _) => tooltip.push("No desk configured; run `desk-control connect`".to_string()),
+                (Some(address), Some(error)) => tooltip.push(format!("Disconnected from {} ({})", address, error)),
+                (Some(address), None) => tooltip.push(format!("Connecting to {}", address)),
+            }
+            ("offline".to_string(), "disconnected")
+        }
+    };
+
+    if let (true, Some(error)) = (state.connected, &state.error) {
+        tooltip.push(format!("Last move failed: {}", error));
+    }
+    for (button, preset) in clicks.bound() {
+        tooltip.push(format!(
+            "{} click: {} ({})",
+            button.name(),
+            preset.name(),
+            config.format_height(config.get_preset(preset))
+        ));
+    }
+
+    Block {
+        text,
+        tooltip: tooltip.join("\n"),
+        class,
+    }
+}
+
+/// The line a bar reads for `block`
+fn format_line(format: BarFormat, block: &Block, clicks: &Clicks, exe: &str) -> Result<String> {
+    match format {
+        BarFormat::Waybar => {
+            #[derive(Serialize)]
+            struct Waybar<'a> {
+                text: &'a str,
+                alt: &'a str,
+                tooltip: &'a str,
+                class: &'a str,
+            }
+            Ok(serde_json::to_string(&Waybar {
+                text: &block.text,
+                alt: block.class,
+                tooltip: &block.tooltip,
+                class: block.class,
+            })?)
+        }
+        BarFormat::Polybar => {
+            // %{A<button>:<command>:}text%{A}, with colons in the command escaped
+            let mut line = block.text.replace('%', "%%");
+            for (button, preset) in clicks.bound() {
+                let command = format!("{} move {}", shell_word(exe), preset.name().to_lowercase()).replace(':', "\\:");
+                line = format!("%{{A{}:{}:}}{}%{{A}}", button.number(), command, line);
+            }
+            Ok(line)
+        }
+        // i3bar ignores keys it doesn't know; they are there for scripts and forks
+        BarFormat::I3blocks => Ok(serde_json::to_string(&serde_json::json!({
+            "full_text": block.text,
+            "short_text": block.text,
+            "tooltip": block.tooltip,
+            "class": block.class,
+        }))?),
+    }
+}
+
+/// `arg` as a single word for the shell Polybar runs click commands with
+fn shell_word(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "/._-+,@=".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Move to the preset bound to `button`, if any
+pub async fn click(config: &Config, clicks: &Clicks, button: Button) -> Result<()> {
+    let Some(preset) = clicks.get(button) else {
+        return Ok(());
+    };
+    let height = config.get_preset(preset);
+    config.move_limits().check(height)?;
+    daemon::connect_or_start(config.clone())
+        .await?
+        .with_source(MoveSource::Bar)
+        .move_to_height(height)
+        .await?;
+    Ok(())
+}
+
+/// Send the daemon's events to `events`, hosting the daemon here if none
+/// runs, and find or become the next one when it goes away
+///
+/// Ends once the receiver of `events` is dropped.
+async fn follow(config: Config, events: mpsc::UnboundedSender<WatchEvent>) {
+    loop {
+        match daemon::connect_or_start(config.clone()).await {
+            Ok(client) => {
+                if let Err(e) = client.subscribe(events.clone()).await {
+                    log::warn!("Lost the desk-control daemon: {}", e);
+                }
+            }
+            Err(e) => log::warn!("{:#}", e),
+        }
+        if events.is_closed() {
+            return;
+        }
+
+        let lost = WatchEvent::Disconnected {
+            error: "desk-control daemon unavailable".to_string(),
+            retry_in_secs: RECONNECT_DELAY.as_secs(),
+        };
+        if events.send(lost).is_err() {
+            return;
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// `desk-control bar`: print the desk's state for a status bar whenever it changes, until Ctrl-C
+pub async fn run(config: &Config, format: BarFormat, clicks: Clicks) -> Result<()> {
+    let exe = std::env::current_exe()?.display().to_string();
+    let mut config = config.clone();
+    let mut state = DeskState {
+        address: config.desk_address.clone(),
+        ..DeskState::default()
+    };
+
+    // Only one process may hold the link, so the bar goes through the daemon
+    let (events, mut received) = mpsc::unbounded_channel();
+    let follower = tokio::spawn(follow(config.clone(), events));
+
+    // i3blocks writes a JSON line to a persistent block's stdin per click
+    let (clicked, mut clicks_received) = mpsc::unbounded_channel();
+    if format == BarFormat::I3blocks {
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(tokio::io::stdin()).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let button = serde_json::from_str::<serde_json::Value>(&line)
+                    .ok()
+                    .and_then(|click| click["button"].as_u64())
+                    .and_then(Button::from_number);
+                if let Some(button) = button {
+                    if clicked.send(button).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+
+    // Presets, click labels and the display unit follow edits to the config
+    let (changes, mut changed) = mpsc::unbounded_channel();
+    tokio::spawn(Config::watch(changes));
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    let stopped = daemon::wait_for_hosted_stop();
+    tokio::pin!(ctrl_c, stopped);
+
+    let mut last = None;
+    loop {
+        let line = format_line(format, &render(&config, &clicks, &state), &clicks, &exe)?;
+        if last.as_ref() != Some(&line) {
+            println!("{}", line);
+            last = Some(line);
+        }
+
+        tokio::select! {
+            event = received.recv() => match event {
+                Some(event) => state.apply(event),
+                None => break,
+            },
+            Some(button) = clicks_received.recv() => {
+                let config = config.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = click(&config, &clicks, button).await {
+                        log::warn!("Click action failed: {:#}", e);
+                    }
+                });
+            }
+            Some(mut reloaded) = changed.recv() => {
+                reloaded.inherit_session_profile(&config);
+                config = reloaded;
+            }
+            _ = &mut ctrl_c => break,
+            _ = &mut stopped => break,
+        }
+    }
+
+    follower.abort();
+    daemon::finish_hosting().await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connected_at(units: u16) -> DeskState {
+        let mut state = DeskState {
+            address: Some("AA:BB:CC:DD:EE:FF".to_string()),
+            ..DeskState::default()
+        };
+        state.apply(WatchEvent::Connected { address: "AA:BB:CC:DD:EE:FF".to_string() });
+        state.apply(WatchEvent::Height {
+            height_mm: Height::from_units(units),
+            speed: 0,
+            moving: false,
+        });
+        state
+    }
+
+    #[test]
+    fn test_classes_follow_posture_and_motion() {
+        let config = Config::default();
+        let clicks = Clicks::default();
+
+        assert_eq!(render(&config, &clicks, &connected_at(7000)).class, "sitting");
+        assert_eq!(render(&config, &clicks, &connected_at(10000)).class, "standing");
+
+        let mut moving = connected_at(9000);
+        moving.apply(WatchEvent::MoveStarted {
+            target_mm: Height::from_units(10500),
+            preset: None,
+        });
+        let block = render(&config, &clicks, &moving);
+        assert_eq!(block.class, "moving");
+        assert!(block.tooltip.starts_with("Moving to"));
+
+        let mut waiting = DeskState::default();
+        waiting.apply(WatchEvent::Connected { address: "AA:BB:CC:DD:EE:FF".to_string() });
+        assert_eq!(render(&config, &clicks, &waiting).class, "connected");
+
+        moving.apply(WatchEvent::Disconnected { error: "gone".to_string(), retry_in_secs: 2 });
+        assert_eq!(render(&config, &clicks, &moving).class, "disconnected");
+        assert_eq!(render(&config, &clicks, &DeskState::default()).text, "offline");
+    }
+
+    #[test]
+    fn test_polybar_wraps_click_actions() {
+        let clicks = Clicks {
+            left: Some(DrinkSize::Grande),
+            right: Some(DrinkSize::Short),
+            ..Clicks::default()
+        };
+        let block = Block {
+            text: "105.0cm".to_string(),
+            tooltip: String::new(),
+            class: "standing",
+        };
+
+        let line = format_line(BarFormat::Polybar, &block, &clicks, "/usr/bin/desk-control").unwrap();
+        assert_eq!(
+            line,
+            "%{A3:/usr/bin/desk-control move short:}%{A1:/usr/bin/desk-control move grande:}105.0cm%{A}%{A}"
+        );
+
+        let line = format_line(BarFormat::Polybar, &block, &clicks, "/opt/My Apps/desk-control").unwrap();
+        assert!(line.starts_with("%{A3:'/opt/My Apps/desk-control' move short:}"), "{}", line);
+        assert_eq!(shell_word("/tmp/it's"), "'/tmp/it'\\''s'");
+
+        let line = format_line(BarFormat::I3blocks, &block, &clicks, "desk-control").unwrap();
+        assert!(line.contains("\"class\":\"standing\""));
+    }
+}
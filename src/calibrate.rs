@@ -0,0 +1,158 @@
+use anyhow::{anyhow, Result};
+use std::io::{self, BufRead, Write};
+
+use crate::config::Config;
+use crate::daemon::Desk;
+use crate::desk::calibration::MAX_SCALE_ERROR;
+use crate::desk::{Calibration, Height, MovementCommand};
+use crate::units::{parse_height, DisplayUnit};
+
+/// How far the desk is raised for the optional second measurement (40cm)
+const VERIFY_TRAVEL: Height = Height::from_units(4000);
+
+/// Scale errors below this are reported as "measures correctly" (0.5%)
+const NEGLIGIBLE_SCALE_ERROR: f64 = 0.005;
+
+/// How the calibration wizard talks to the user (terminal or tray dialogs)
+pub trait Prompt {
+    /// Tell the user what is happening
+    fn say(&self, message: &str);
+
+    /// Ask a yes/no question
+    async fn confirm(&self, question: &str) -> bool;
+
+    /// Ask for a line of text; `None` if the user cancelled
+    async fn ask(&self, question: &str) -> Option<String>;
+}
+
+/// Walk the user through measuring the desk and work out its calibration
+///
+/// Drives the desk to its lowest position, asks for a tape measurement and
+/// optionally a second one about 40cm higher. Returns `None` if the user
+/// cancelled; nothing is saved here, see [`save`].
+pub async fn run<P: Prompt>(desk: &Desk, unit: DisplayUnit, prompt: &P) -> Result<Option<Calibration>> {
+    if !prompt
+        .confirm("The desk will move down to its lowest position. Make sure nothing is underneath it. Continue?")
+        .await
+    {
+        return Ok(None);
+    }
+
+    prompt.say("Moving to the lowest position...");
+    desk.move_to_end_stop(MovementCommand::Down).await?;
+    let low = desk.get_raw_height().await?;
+
+    let question = format!(
+        "Measure from the floor to the top of the desk. How high is it now (e.g. 64.5{})?",
+        unit.suffix()
+    );
+    let Some(low_measured) = ask_height(prompt, &question, unit).await else {
+        return Ok(None);
+    };
+
+    if !prompt
+        .confirm("Verify at a second height? The desk will move up about 40cm, which also checks that it measures distances correctly.")
+        .await
+    {
+        return Ok(Some(Calibration::from_point(low, low_measured)));
+    }
+
+    prompt.say("Moving up...");
+    let current = desk.get_height().await?;
+    desk.move_to_height(Height::from_units(current.units().saturating_add(VERIFY_TRAVEL.units())))
+        .await?;
+    let high = desk.get_raw_height().await?;
+
+    let Some(high_measured) = ask_height(prompt, "How high is the desk now?", unit).await else {
+        return Ok(None);
+    };
+
+    Calibration::from_points((low, low_measured), (high, high_measured))
+        .map(Some)
+        .ok_or_else(|| {
+            anyhow!(
+                "The two measurements differ from the desk's movement by more than {:.0}%. \
+                Check the measurements and run the calibration again.",
+                MAX_SCALE_ERROR * 100.0
+            )
+        })
+}
+
+/// Ask for a height until the answer parses or the user cancels
+async fn ask_height<P: Prompt>(prompt: &P, question: &str, unit: DisplayUnit) -> Option<Height> {
+    loop {
+        let answer = prompt.ask(question).await?;
+        match parse_height(&answer, unit) {
+            Ok(height) => return Some(height),
+            Err(e) => prompt.say(&format!("{}. Try again, e.g. 64.5{}", e, unit.suffix())),
+        }
+    }
+}
+
+/// Store `calibration` (or drop it with `None`), keeping presets at their desk positions
+pub fn save(calibration: Option<Calibration>) -> Result<Config> {
+    Config::try_update(|config| {
+        config.recalibrate(calibration);
+        config.validate()
+    })
+}
+
+/// One-paragraph description of a calibration for the user
+pub fn summary(calibration: &Calibration, config: &Config) -> String {
+    let lowest = config.format_height(calibration.to_real(Height::default()));
+    let error = calibration.scale_error();
+
+    let scale = if error.abs() < NEGLIGIBLE_SCALE_ERROR {
+        "The desk measures distances correctly.".to_string()
+    } else {
+        format!(
+            "The desk reports distances {:.1}% too {}; heights are corrected for that.",
+            error.abs() * 100.0,
+            if error > 0.0 { "short" } else { "long" }
+        )
+    };
+
+    format!(
+        "Calibrated: the desk reads 0 at {}. {} Presets still point at the same desk positions; \
+        review them with `desk-control status`.",
+        lowest, scale
+    )
+}
+
+/// Asks the wizard's questions on stdin/stdout
+pub struct TerminalPrompt;
+
+impl TerminalPrompt {
+    /// Print `question` and read one line, `None` at end of input
+    async fn read_line(question: String) -> Option<String> {
+        tokio::task::spawn_blocking(move || {
+            print!("{} ", question);
+            io::stdout().flush().ok()?;
+
+            let mut line = String::new();
+            match io::stdin().lock().read_line(&mut line) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line.trim().to_string()),
+            }
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+}
+
+impl Prompt for TerminalPrompt {
+    fn say(&self, message: &str) {
+        println!("{}", message);
+    }
+
+    async fn confirm(&self, question: &str) -> bool {
+        Self::read_line(format!("{} [y/N]", question))
+            .await
+            .is_some_and(|answer| answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"))
+    }
+
+    async fn ask(&self, question: &str) -> Option<String> {
+        Self::read_line(question.to_string()).await.filter(|answer| !answer.is_empty())
+    }
+}
@@ -0,0 +1,879 @@
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::autostart;
+use crate::bar::{self, BarFormat, Button, Clicks};
+use crate::calibrate::{self, TerminalPrompt};
+use crate::config::{Config, DrinkSize, Profile};
+use crate::config_keys::ConfigKey;
+use crate::daemon::{self, DaemonClient, Desk};
+use crate::desk::scan::Detection;
+use crate::desk::watch::WatchEvent;
+use crate::desk::{DeskController, DeskError, DeskResult, Height, MovementCommand, ScanReport};
+use crate::doctor;
+use crate::movement::MoveRequest;
+use crate::range;
+#[cfg(target_os = "linux")]
+use crate::systemd;
+use crate::ui::tui;
+
+/// Control a Linak standing desk from the system tray or the command line
+#[derive(Debug, Parser)]
+#[command(name = "desk-control", version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub global: GlobalArgs,
+
+    /// Run a command instead of starting the tray app
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Options accepted by every subcommand
+#[derive(Debug, Args)]
+pub struct GlobalArgs {
+    /// Profile to use instead of the configured active profile
+    #[arg(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    pub json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Move the desk to a preset (grande), a height (105cm) or by an amount (+2cm, -0.5in)
+    Move {
+        /// Preset name, height, or signed change; bare numbers use the display unit
+        #[arg(allow_hyphen_values = true)]
+        target: String,
+    },
+
+    /// Raise the desk for a short time
+    Up {
+        /// How long to keep moving, in seconds
+        #[arg(long, default_value_t = 1.0)]
+        seconds: f32,
+    },
+
+    /// Lower the desk for a short time
+    Down {
+        /// How long to keep moving, in seconds
+        #[arg(long, default_value_t = 1.0)]
+        seconds: f32,
+    },
+
+    /// Stop any desk movement
+    Stop,
+
+    /// Print the current desk height
+    Height,
+
+    /// Show the configuration and whether the desk is reachable
+    Status,
+
+    /// Follow the desk's height, speed and connection state until Ctrl-C
+    Watch,
+
+    /// Full-screen terminal UI with live height, presets and hold-to-move
+    Tui,
+
+    /// Keep printing the desk's state for a Waybar, Polybar or i3blocks module
+    Bar {
+        /// Status bar to format the output for
+        #[arg(long, value_enum, default_value_t = BarFormat::Waybar)]
+        format: BarFormat,
+
+        /// Preset to move to on a left click
+        #[arg(long, value_parser = parse_preset)]
+        left: Option<DrinkSize>,
+
+        /// Preset to move to on a middle click
+        #[arg(long, value_parser = parse_preset)]
+        middle: Option<DrinkSize>,
+
+        /// Preset to move to on a right click
+        #[arg(long, value_parser = parse_preset)]
+        right: Option<DrinkSize>,
+
+        /// Run the action bound to this button and exit, e.g. from Waybar's `on-click`
+        #[arg(long, value_enum)]
+        click: Option<Button>,
+    },
+
+    /// Scan for nearby desks and show what each one advertises
+    Scan {
+        /// Scan duration in seconds
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+
+        /// Include Bluetooth devices that don't look like a desk
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Connect to a desk and save it as the configured desk
+    Connect {
+        /// Bluetooth address; the first desk found is used if omitted
+        address: Option<String>,
+
+        /// Then drive the desk to both end stops to find its real height range
+        #[arg(long)]
+        discover_range: bool,
+    },
+
+    /// Measure the desk with a tape measure so heights match reality
+    Calibrate {
+        /// Forget the calibration and use the desk's own readings again
+        #[arg(long)]
+        reset: bool,
+    },
+
+    /// Check Bluetooth, tray and config setup and suggest fixes
+    Doctor,
+
+    /// Keep the desk connected and serve the tray, CLI and scripts over a local socket
+    Daemon,
+
+    /// Manage height presets
+    #[command(subcommand)]
+    Preset(PresetCommand),
+
+    /// Manage the configuration file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Run desk-control as a systemd user service
+    #[cfg(target_os = "linux")]
+    #[command(subcommand)]
+    Systemd(SystemdCommand),
+
+    /// Start the tray app when you log in to the desktop
+    #[command(subcommand)]
+    Autostart(AutostartCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PresetCommand {
+    /// Store the desk's current height as a preset
+    Save {
+        /// Preset to overwrite (short, tall, grande or venti)
+        #[arg(value_parser = parse_preset)]
+        preset: DrinkSize,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print one setting, e.g. `presets.grande` or `desk_address`
+    Get {
+        key: String,
+    },
+
+    /// Change a setting; heights accept a unit (104cm, 41in)
+    Set {
+        key: String,
+        value: String,
+    },
+
+    /// Clear a setting, or reset it to its default
+    Unset {
+        key: String,
+    },
+
+    /// Print every setting with its dotted key
+    List,
+
+    /// Add a profile with the default presets, limits and reminders
+    AddProfile {
+        name: String,
+    },
+
+    /// Restore the configuration from one of the automatic backups
+    Restore {
+        /// Backup to restore (1 = most recent)
+        #[arg(default_value_t = 1)]
+        backup: usize,
+
+        /// List the available backups instead of restoring one
+        #[arg(long)]
+        list: bool,
+    },
+}
+
+#[cfg(target_os = "linux")]
+#[derive(Debug, Subcommand)]
+pub enum SystemdCommand {
+    /// Write a user unit that starts the daemon at login
+    Install {
+        /// Start the tray app instead, with the graphical session
+        #[arg(long)]
+        tray: bool,
+    },
+
+    /// Remove the user unit again
+    Uninstall {
+        /// Remove the tray app's unit instead of the daemon's
+        #[arg(long)]
+        tray: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AutostartCommand {
+    /// Add a login item for the tray app (XDG autostart, or a launch agent on macOS)
+    Enable,
+
+    /// Remove the login item again
+    Disable,
+
+    /// Show whether the tray app starts at login
+    Status,
+}
+
+/// Run a command-line subcommand to completion
+pub fn run(command: Command, global: &GlobalArgs) -> Result<()> {
+    let output = Output { json: global.json };
+
+    match command {
+        Command::Config(ConfigCommand::Get { key }) => {
+            let config = load_config(global)?;
+            let key = key.parse::<ConfigKey>()?;
+            if output.json {
+                return output.json(&key.get(&config)?);
+            }
+            println!("{}", key.get_text(&config)?);
+            Ok(())
+        }
+        Command::Config(ConfigCommand::Set { key, value }) => {
+            edit_config(global, output, &key, |key, config| key.set(config, &value))
+        }
+        Command::Config(ConfigCommand::Unset { key }) => {
+            edit_config(global, output, &key, |key, config| key.unset(config))
+        }
+        Command::Config(ConfigCommand::List) => list_config(&load_config(global)?, output),
+        Command::Config(ConfigCommand::AddProfile { name }) => add_profile(&name, output),
+        Command::Config(ConfigCommand::Restore { list: true, .. }) => list_backups(),
+        Command::Config(ConfigCommand::Restore { backup, .. }) => {
+            let config = Config::restore_backup(backup)?;
+            println!("Restored configuration from backup {}", backup);
+            println!("{}", serde_json::to_string_pretty(&config)?);
+            Ok(())
+        }
+        #[cfg(target_os = "linux")]
+        Command::Systemd(SystemdCommand::Install { tray }) => install_unit(tray, output),
+        #[cfg(target_os = "linux")]
+        Command::Systemd(SystemdCommand::Uninstall { tray }) => uninstall_unit(tray, output),
+        Command::Autostart(command) => autostart(command, output),
+        Command::Doctor => match doctor::run(global.json)? {
+            true => Ok(()),
+            false => Err(anyhow!("Some checks failed")),
+        },
+        Command::Tui => tui::run(load_config(global)?),
+        Command::Daemon => block_on(daemon::run(load_config(global)?)),
+        Command::Calibrate { reset: true } => {
+            let config = calibrate::save(None)?;
+            if output.json {
+                return output.json(&serde_json::json!({ "calibration": config.calibration }));
+            }
+            println!("Calibration removed; heights are the desk's own readings again");
+            Ok(())
+        }
+        Command::Scan { timeout, all } => {
+            // Only to mark the configured desk, so scanning doesn't create a config
+            let configured = Config::read_existing()?.and_then(|config| config.desk_address);
+            block_on(scan(configured.as_deref(), output, timeout, all))
+        }
+        Command::Move { target } => {
+            with_config(global, |config| async move { move_to(&config, &target, output).await })
+        }
+        Command::Up { seconds } => {
+            with_config(global, |config| async move { nudge(&config, MovementCommand::Up, seconds, output).await })
+        }
+        Command::Down { seconds } => {
+            with_config(global, |config| async move { nudge(&config, MovementCommand::Down, seconds, output).await })
+        }
+        Command::Stop => with_config(global, |config| async move {
+            let desk = connect(&config).await?;
+            desk.stop().await?;
+            output.print(&config, "Stopped", desk.get_height().await?)
+        }),
+        Command::Height => with_config(global, |config| async move {
+            let desk = connect(&config).await?;
+            output.print(&config, "Current height", desk.get_height().await?)
+        }),
+        Command::Status => with_config(global, |config| async move { status(&config, output).await }),
+        Command::Watch => with_config(global, |config| async move { watch_desk(&config, output).await }),
+        Command::Bar {
+            format,
+            left,
+            middle,
+            right,
+            click,
+        } => with_config(global, |config| async move {
+            let clicks = Clicks { left, middle, right };
+            match click {
+                Some(button) => bar::click(&config, &clicks, button).await,
+                None => bar::run(&config, format, clicks).await,
+            }
+        }),
+        Command::Connect {
+            address,
+            discover_range,
+        } => block_on(connect_and_save(address, discover_range, output)),
+        // The wizard asks questions on the terminal, which would garble the JSON
+        Command::Calibrate { reset: false } if output.json => {
+            Err(anyhow!("`calibrate` is interactive and has no JSON output; `calibrate --reset --json` does"))
+        }
+        Command::Calibrate { reset: false } => with_config(global, |config| async move { calibrate(&config).await }),
+        Command::Preset(PresetCommand::Save { preset }) => {
+            with_config(global, |config| async move { save_current_height(config, preset, output).await })
+        }
+    }
+}
+
+/// Load the config with the profile selected for this run
+fn load_config(global: &GlobalArgs) -> Result<Config> {
+    let mut config = Config::load()?;
+    config.select_session_profile(global.profile.as_deref())?;
+    Ok(config)
+}
+
+/// Run an async command with the config for this run
+fn with_config<F, Fut>(global: &GlobalArgs, command: F) -> Result<()>
+where
+    F: FnOnce(Config) -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    let config = load_config(global)?;
+    block_on(command(config))
+}
+
+/// Apply one key's change to the config on disk, refusing invalid results
+///
+/// Profile keys without a `profiles.<name>.` prefix edit the profile in use
+/// (`--profile` or the active one). A running tray picks the change up.
+fn edit_config<F>(global: &GlobalArgs, output: Output, key: &str, change: F) -> Result<()>
+where
+    F: FnOnce(&ConfigKey, &mut Config) -> Result<()>,
+{
+    let key = key
+        .parse::<ConfigKey>()?
+        .for_profile(load_config(global)?.active_profile_name());
+
+    let updated = Config::try_update(|config| {
+        change(&key, config)?;
+        config.validate()
+    })?;
+
+    if output.json {
+        return output.json(&serde_json::json!({
+            "key": key.to_string(),
+            "value": key.get(&updated)?,
+        }));
+    }
+    println!("{} = {}", key, key.get_text(&updated)?);
+    Ok(())
+}
+
+/// Create a profile for `config set profiles.<name>...` to fill in
+fn add_profile(name: &str, output: Output) -> Result<()> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow!("Profile names can't be empty"));
+    }
+    Config::try_update(|config| {
+        if config.profiles.contains_key(name) {
+            return Err(anyhow!("Profile '{}' already exists", name));
+        }
+        config.profiles.insert(name.to_string(), Profile::default());
+        Ok(())
+    })?;
+
+    if output.json {
+        return output.json(&serde_json::json!({ "profile": name }));
+    }
+    println!("Added profile '{}'; change its settings with `config set profiles.{}.<key>`", name, name);
+    Ok(())
+}
+
+fn list_config(config: &Config, output: Output) -> Result<()> {
+    let keys = ConfigKey::all(config);
+
+    if output.json {
+        let values = keys
+            .iter()
+            .map(|key| Ok((key.to_string(), key.get(config)?)))
+            .collect::<Result<serde_json::Map<_, _>>>()?;
+        return output.json(&values);
+    }
+
+    for key in keys {
+        println!("{} = {}", key, key.get_text(config)?);
+    }
+    Ok(())
+}
+
+/// Run a future on a fresh Tokio runtime
+fn block_on<F: std::future::Future<Output = Result<()>>>(future: F) -> Result<()> {
+    tokio::runtime::Runtime::new()?.block_on(future)
+}
+
+/// Reach the desk through a running daemon, or connect to it directly
+async fn connect(config: &Config) -> DeskResult<Desk> {
+    Desk::connect(config).await
+}
+
+async fn move_to(config: &Config, target: &str, output: Output) -> Result<()> {
+    let request = MoveRequest::parse(target, config.display_unit)?;
+
+    // Reject out-of-range targets before spending time on a connection
+    if !request.is_relative() {
+        request.resolve(config, Height::default())?;
+    }
+
+    let desk = connect(config).await?;
+    let height = request.resolve(config, desk.get_height().await?)?;
+    desk.move_to_height(height).await?;
+    let reached = desk.get_height().await?;
+
+    if output.json {
+        #[derive(Serialize)]
+        struct MoveReport {
+            request: String,
+            target_mm: Height,
+            height_mm: Height,
+        }
+        return output.json(&MoveReport {
+            request: request.to_string(),
+            target_mm: height,
+            height_mm: reached,
+        });
+    }
+
+    let label = match request {
+        MoveRequest::Preset(preset) => format!("Moved to {} preset", preset.name()),
+        _ => "Moved".to_string(),
+    };
+    output.print(config, &label, reached)
+}
+
+async fn nudge(config: &Config, direction: MovementCommand, seconds: f32, output: Output) -> Result<()> {
+    let duration = Duration::try_from_secs_f32(seconds)
+        .map_err(|_| anyhow!("Invalid duration: {} seconds", seconds))?;
+
+    let desk = connect(config).await?;
+    desk.move_for(direction, duration).await?;
+    output.print(config, "Current height", desk.get_height().await?)
+}
+
+async fn status(config: &Config, output: Output) -> Result<()> {
+    #[derive(Serialize)]
+    struct StatusReport {
+        desk_address: Option<String>,
+        profile: String,
+        display_unit: String,
+        presets: BTreeMap<String, Height>,
+        connected: bool,
+        height_mm: Option<Height>,
+        error: Option<String>,
+        /// Whether a daemon holds the connection
+        daemon: bool,
+    }
+
+    // Ask a running daemon rather than waiting for it to reconnect
+    let daemon = DaemonClient::find().await;
+    let (height, error) = match &daemon {
+        Some(client) => match client.status().await {
+            Ok(status) => (status.height_mm, status.error),
+            Err(e) => (None, Some(e.to_string())),
+        },
+        None => match connect(config).await {
+            Ok(desk) => match desk.get_height().await {
+                Ok(height) => (Some(height), None),
+                Err(e) => (None, Some(e.to_string())),
+            },
+            Err(e) => (None, Some(e.to_string())),
+        },
+    };
+
+    let report = StatusReport {
+        desk_address: config.desk_address.clone(),
+        profile: config.active_profile_name().to_string(),
+        display_unit: config.display_unit.to_string(),
+        presets: DrinkSize::all()
+            .into_iter()
+            .map(|preset| (preset.name().to_lowercase(), config.get_preset(preset)))
+            .collect(),
+        connected: height.is_some(),
+        height_mm: height,
+        error,
+        daemon: daemon.is_some(),
+    };
+
+    if output.json {
+        return output.json(&report);
+    }
+
+    println!("Desk:      {}", report.desk_address.as_deref().unwrap_or("not configured"));
+    println!("Profile:   {}", report.profile);
+    for preset in DrinkSize::all() {
+        println!("  {:<8} {}", preset.name(), config.format_height(config.get_preset(preset)));
+    }
+    match (height, &report.error) {
+        (Some(height), _) => println!("Connected: yes ({})", config.format_height(height)),
+        (None, Some(error)) => println!("Connected: no ({})", error),
+        (None, None) => println!("Connected: no"),
+    }
+    if report.daemon {
+        println!("Daemon:    running");
+    }
+    Ok(())
+}
+
+async fn watch_desk(config: &Config, output: Output) -> Result<()> {
+    let (events, mut received) = mpsc::unbounded_channel();
+    let watcher = daemon::follow(config, events).await?;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    loop {
+        tokio::select! {
+            event = received.recv() => match event {
+                Some(event) if output.json => output.json(&event)?,
+                Some(event) => print_watch_event(config, &event),
+                None => break,
+            },
+            _ = &mut ctrl_c => break,
+        }
+    }
+
+    // Dropping the receiver tells the watcher to disconnect and return
+    drop(received);
+    let _ = tokio::time::timeout(Duration::from_secs(3), watcher).await;
+    Ok(())
+}
+
+fn print_watch_event(config: &Config, event: &WatchEvent) {
+    match event {
+        WatchEvent::Connecting { address } => println!("Connecting to {}...", address),
+        WatchEvent::Connected { address } => println!("Connected to {}", address),
+        WatchEvent::Height { height_mm, speed, .. } => {
+            let motion = match speed.signum() {
+                1 => "  moving up",
+                -1 => "  moving down",
+                _ => "",
+            };
+            println!("{}{}", config.format_height(*height_mm), motion);
+        }
+        WatchEvent::Disconnected { error, retry_in_secs } => {
+            println!("Disconnected ({}), reconnecting in {}s", error, retry_in_secs)
+        }
+        WatchEvent::MoveStarted { target_mm, .. } => {
+            println!("Moving to {}", config.format_height(*target_mm))
+        }
+        WatchEvent::MoveFinished { height_mm, .. } => {
+            println!("Reached {}", config.format_height(*height_mm))
+        }
+        WatchEvent::MoveFailed { error, .. } => println!("Move failed: {}", error),
+    }
+}
+
+async fn scan(configured: Option<&str>, output: Output, timeout: u64, all: bool) -> Result<()> {
+    let reports = DeskController::scan(timeout, configured, all).await?;
+
+    if output.json {
+        return output.json(&reports);
+    }
+
+    if reports.is_empty() {
+        println!("No desks found");
+    }
+    for report in &reports {
+        print_scan_report(report);
+    }
+    Ok(())
+}
+
+fn print_scan_report(report: &ScanReport) {
+    let rssi = report
+        .rssi
+        .map(|rssi| format!("{} dBm", rssi))
+        .unwrap_or_else(|| "? dBm".to_string());
+    let kind = match (report.vendor, report.detected_by) {
+        (Some(vendor), Some(Detection::Name)) => format!("{} (by name)", vendor),
+        (Some(vendor), _) => vendor.to_string(),
+        (None, _) => "not a desk".to_string(),
+    };
+
+    println!(
+        "{}  {:<20} {:>8}  {}{}",
+        report.address,
+        report.name.as_deref().unwrap_or("(unnamed)"),
+        rssi,
+        kind,
+        if report.configured { "  [configured]" } else { "" }
+    );
+    for service in &report.services {
+        println!("    service       {}", service);
+    }
+    for (company, data) in &report.manufacturer_data {
+        println!("    manufacturer  0x{:04x}: {}", company, data);
+    }
+}
+
+async fn connect_and_save(address: Option<String>, discover_range: bool, output: Output) -> Result<()> {
+    // Only one process may hold the link: a running daemon connects for us
+    let (desk, address, mut config) = match DaemonClient::find().await {
+        Some(client) => {
+            let address = match address {
+                Some(address) => address,
+                None => find_desk().await?,
+            };
+            // Saved through the daemon, so it switches to the desk right away
+            let params = serde_json::json!({ "key": "desk_address", "value": address });
+            client.call::<serde_json::Value>("config.set", params).await?;
+            wait_for_daemon_connection(&client, &address).await?;
+            (Desk::Daemon(client), address, Config::load()?)
+        }
+        None => {
+            let desk = DeskController::connect(address, Config::load()?.calibration()).await?;
+            let address = desk.address();
+            let config = Config::update(|config| config.set_desk_address(&address))?;
+            (Desk::Direct(desk), address, config)
+        }
+    };
+
+    if !output.json {
+        println!("Connected to {} and saved it as the configured desk", address);
+    }
+
+    if discover_range {
+        if let Some(found) = range::discover(&desk, &TerminalPrompt).await? {
+            let (updated, adjusted) = range::save(found)?;
+            if !output.json {
+                println!("{}", range::summary(&found, &updated, &adjusted));
+            }
+            config = updated;
+        }
+    }
+
+    if output.json {
+        return output.json(&serde_json::json!({
+            "desk_address": address,
+            "desk_range": config.desk_range,
+        }));
+    }
+    output.print(&config, "Current height", desk.get_height().await?)
+}
+
+/// Address of the nearest desk, found by scanning without connecting
+async fn find_desk() -> Result<String> {
+    let reports = DeskController::scan(10, None, false).await?;
+    let report = reports.first().ok_or(DeskError::NotFound)?;
+    Ok(report.address.clone())
+}
+
+/// Wait until the daemon is connected to `address`
+async fn wait_for_daemon_connection(client: &DaemonClient, address: &str) -> Result<()> {
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    let connected = async {
+        loop {
+            let status = client.status().await?;
+            if status.connected && status.desk_address.as_deref() == Some(address) {
+                return DeskResult::Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    };
+    tokio::time::timeout(CONNECT_TIMEOUT, connected)
+        .await
+        .map_err(|_| DeskError::Timeout {
+            operation: "waiting for the daemon to connect",
+            after: CONNECT_TIMEOUT,
+        })??;
+    Ok(())
+}
+
+async fn save_current_height(config: Config, preset: DrinkSize, output: Output) -> Result<()> {
+    let desk = connect(&config).await?;
+    let height = desk.get_height().await?;
+    let updated = config.save_preset(preset, height)?;
+
+    if output.json {
+        return output.json(&serde_json::json!({
+            "preset": preset.name(),
+            "profile": updated.active_profile_name(),
+            "height_mm": height,
+        }));
+    }
+
+    println!(
+        "Saved {} as {} preset (profile: {})",
+        updated.format_height(height),
+        preset.name(),
+        updated.active_profile_name()
+    );
+    Ok(())
+}
+
+async fn calibrate(config: &Config) -> Result<()> {
+    let desk = connect(config).await?;
+    let Some(calibration) = calibrate::run(&desk, config.display_unit, &TerminalPrompt).await? else {
+        println!("Calibration cancelled, nothing was changed");
+        return Ok(());
+    };
+
+    let updated = calibrate::save(Some(calibration))?;
+    println!("{}", calibrate::summary(&calibration, &updated));
+    Ok(())
+}
+
+/// Prints results either as text or as JSON
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    /// Print a labelled height, e.g. "Current height: 105.0cm"
+    fn print(&self, config: &Config, label: &str, height: Height) -> Result<()> {
+        if self.json {
+            return self.json(&serde_json::json!({
+                "height_mm": height,
+                "height": config.format_height(height),
+            }));
+        }
+        println!("{}: {}", label, config.format_height(height));
+        Ok(())
+    }
+
+    fn json<T: Serialize>(&self, value: &T) -> Result<()> {
+        println!("{}", serde_json::to_string(value)?);
+        Ok(())
+    }
+}
+
+fn parse_preset(name: &str) -> Result<DrinkSize, String> {
+    DrinkSize::from_name(name).ok_or_else(|| {
+        format!("unknown preset '{}' (expected short, tall, grande or venti)", name)
+    })
+}
+
+fn list_backups() -> Result<()> {
+    let backups = Config::backups()?;
+
+    if backups.is_empty() {
+        println!("No configuration backups found");
+        return Ok(());
+    }
+
+    for (index, path) in backups {
+        let modified = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .map(|age| format!("{} ago", format_age(age.as_secs())))
+            .unwrap_or_else(|| "unknown age".to_string());
+        println!("{}  {}  ({})", index, path.display(), modified);
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn install_unit(tray: bool, output: Output) -> Result<()> {
+    let path = systemd::install(tray)?;
+    if output.json {
+        return output.json(&serde_json::json!({ "unit": path }));
+    }
+    println!("Wrote {}", path.display());
+    println!("Enable it with:");
+    println!("  systemctl --user daemon-reload");
+    println!("  systemctl --user enable --now {}", systemd::unit_name(tray));
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn uninstall_unit(tray: bool, output: Output) -> Result<()> {
+    let uninstalled = systemd::uninstall(tray)?;
+    if output.json {
+        return output.json(&serde_json::json!({
+            "unit": uninstalled.as_ref().map(|uninstalled| &uninstalled.path),
+            "disabled": uninstalled.as_ref().is_some_and(|uninstalled| uninstalled.disabled),
+        }));
+    }
+    match uninstalled {
+        Some(uninstalled) => {
+            println!("Removed {}", uninstalled.path.display());
+            if !uninstalled.disabled {
+                println!("systemd could not disable it; to remove what is left, run:");
+                println!("  systemctl --user disable --now {}", systemd::unit_name(tray));
+                println!("  systemctl --user daemon-reload");
+            }
+        }
+        None => println!("{} is not installed", systemd::unit_name(tray)),
+    }
+    Ok(())
+}
+
+fn autostart(command: AutostartCommand, output: Output) -> Result<()> {
+    match command {
+        AutostartCommand::Enable => {
+            let path = autostart::enable()?;
+            if output.json {
+                return output.json(&serde_json::json!({ "enabled": true, "path": path }));
+            }
+            println!("Wrote {}", path.display());
+            println!("The tray app will start the next time you log in");
+        }
+        AutostartCommand::Disable => {
+            let path = autostart::disable()?;
+            if output.json {
+                return output.json(&serde_json::json!({ "enabled": false, "path": path }));
+            }
+            match path {
+                Some(path) => println!("Removed {}", path.display()),
+                None => println!("Autostart is not enabled"),
+            }
+        }
+        AutostartCommand::Status => {
+            let status = autostart::status()?;
+            if output.json {
+                return output.json(&serde_json::json!({
+                    "enabled": status.enabled,
+                    "path": status.path,
+                    "current": status.current,
+                }));
+            }
+            match (status.enabled, status.current) {
+                (false, _) => println!("Autostart is disabled"),
+                (true, true) => println!("Autostart is enabled ({})", status.path.display()),
+                (true, false) => {
+                    println!(
+                        "Autostart is enabled ({}), but for another desk-control executable",
+                        status.path.display()
+                    );
+                    println!("Run `desk-control autostart enable` to start this one instead");
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Format a duration in seconds as a short human-readable age
+fn format_age(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
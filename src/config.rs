@@ -1,7 +1,22 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use fs2::FileExt;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::desk::{Calibration, DeskError, DeskResult, Height};
+use crate::units::DisplayUnit;
+
+/// Number of rotated backups kept next to the config file (config.bak.1 is newest)
+pub const MAX_BACKUPS: usize = 5;
+
+/// Name of the profile created for configs without any profiles
+pub const DEFAULT_PROFILE: &str = "default";
 
 /// Configuration for the desk control application
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,39 +24,349 @@ pub struct Config {
     /// Bluetooth MAC address or device name of the desk
     pub desk_address: Option<String>,
 
-    /// Height presets mapped to Starbucks drink sizes (in millimeters)
+    /// Unit used to show heights in the tray, notifications and CLI
+    #[serde(default)]
+    pub display_unit: DisplayUnit,
+
+    /// Name of the profile used when none is selected on the command line
+    #[serde(default = "default_profile_name")]
+    pub active_profile: String,
+
+    /// Select the profile named after the OS user at startup, if there is one
+    #[serde(default)]
+    pub auto_select_profile: bool,
+
+    /// Per-person presets, limits and reminder settings, keyed by name
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// Offset and scale measured by `desk-control calibrate`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub calibration: Option<Calibration>,
+
+    /// Range found by driving the desk to its end stops
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desk_range: Option<DeskRange>,
+
+    /// Local HTTP and WebSocket API, off unless enabled
+    #[serde(default)]
+    pub http: HttpSettings,
+
+    /// MQTT bridge for Home Assistant and other home automation
+    #[serde(default)]
+    pub mqtt: MqttSettings,
+
+    /// Shell commands run by the daemon when the desk does something
+    #[serde(default)]
+    pub hooks: HookSettings,
+
+    /// Presets from configs written before profiles existed
+    #[serde(default, rename = "presets", skip_serializing)]
+    legacy_presets: Option<HeightPresets>,
+
+    /// Profile chosen for this process only (--profile or auto-selection)
+    #[serde(skip)]
+    session_profile: Option<String>,
+}
+
+/// Settings for one person using the desk
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    /// Height presets mapped to Starbucks drink sizes
+    #[serde(default)]
     pub presets: HeightPresets,
+
+    /// Heights the desk may be moved between
+    #[serde(default)]
+    pub limits: HeightLimits,
+
+    /// Periodic reminders to change position
+    #[serde(default)]
+    pub reminders: ReminderSettings,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HeightPresets {
     /// Short (8 oz) - typically sitting height
-    pub short: u16,
+    pub short: Height,
 
     /// Tall (12 oz) - mid-level height
-    pub tall: u16,
+    pub tall: Height,
 
     /// Grande (16 oz) - standing height
-    pub grande: u16,
+    pub grande: Height,
 
     /// Venti (20 oz) - maximum height
-    pub venti: u16,
+    pub venti: Height,
+}
+
+impl Default for HeightPresets {
+    fn default() -> Self {
+        Self {
+            short: Height::from_units(6500),   // 65.0 cm - typical sitting height
+            tall: Height::from_units(8500),    // 85.0 cm - mid-level
+            grande: Height::from_units(10500), // 105.0 cm - standing height
+            venti: Height::from_units(12500),  // 125.0 cm - maximum height
+        }
+    }
+}
+
+impl HeightPresets {
+    /// Height stored for `preset`
+    pub fn get(&self, preset: DrinkSize) -> Height {
+        match preset {
+            DrinkSize::Short => self.short,
+            DrinkSize::Tall => self.tall,
+            DrinkSize::Grande => self.grande,
+            DrinkSize::Venti => self.venti,
+        }
+    }
+
+    /// Mutable access to the height stored for `preset`
+    pub fn get_mut(&mut self, preset: DrinkSize) -> &mut Height {
+        match preset {
+            DrinkSize::Short => &mut self.short,
+            DrinkSize::Tall => &mut self.tall,
+            DrinkSize::Grande => &mut self.grande,
+            DrinkSize::Venti => &mut self.venti,
+        }
+    }
+
+    /// Whether someone at `height` is sitting or standing: it counts as
+    /// standing from halfway between the short and grande presets up
+    pub fn posture(&self, height: Height) -> Posture {
+        let threshold = (self.short.as_mm() + self.grande.as_mm()) / 2.0;
+        match height.as_mm() >= threshold {
+            true => Posture::Standing,
+            false => Posture::Sitting,
+        }
+    }
+
+    /// The default presets, squeezed proportionally into `range` where they don't fit
+    ///
+    /// All of them are unchanged if the desk reaches every one. Otherwise all
+    /// of them move, even those within reach, so they keep their order and spacing.
+    pub fn defaults_within(range: DeskRange) -> Self {
+        let defaults = Self::default();
+        let (low, high) = (defaults.short, defaults.venti);
+
+        let mut target_low = low.max(range.min);
+        let mut target_high = high.min(range.max);
+        if target_high <= target_low {
+            (target_low, target_high) = (range.min, range.max);
+        }
+
+        let mut presets = defaults.clone();
+        for preset in DrinkSize::all() {
+            let fraction = (defaults.get(preset).as_mm() - low.as_mm()) / (high.as_mm() - low.as_mm());
+            let mm = target_low.as_mm() + fraction * (target_high.as_mm() - target_low.as_mm());
+            *presets.get_mut(preset) = Height::from_mm_f64(mm).unwrap_or(target_low);
+        }
+        presets
+    }
+}
+
+/// Lowest and highest height the desk can physically reach
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeskRange {
+    pub min: Height,
+    pub max: Height,
+}
+
+/// Lowest and highest height a move may target (unset = no limit)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeightLimits {
+    /// Lowest height a move may target, e.g. to keep knees clear of the desk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<Height>,
+
+    /// Highest height a move may target, e.g. below a shelf or window sill
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<Height>,
+}
+
+impl HeightLimits {
+    /// Fail if `height` lies outside the configured limits
+    pub fn check(&self, height: Height) -> DeskResult<()> {
+        if let Some(min) = self.min.filter(|min| height < *min) {
+            return Err(DeskError::BelowMinimum { height, min });
+        }
+        if let Some(max) = self.max.filter(|max| height > *max) {
+            return Err(DeskError::AboveMaximum { height, max });
+        }
+        Ok(())
+    }
+
+    /// Bring `height` inside the configured limits
+    pub fn clamp(&self, height: Height) -> Height {
+        let height = self.min.map_or(height, |min| height.max(min));
+        self.max.map_or(height, |max| height.min(max))
+    }
+}
+
+/// Reminder notifications to switch between sitting and standing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReminderSettings {
+    pub enabled: bool,
+
+    /// Minutes between reminders
+    pub interval_minutes: u32,
+}
+
+impl Default for ReminderSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_minutes: 45,
+        }
+    }
+}
+
+/// HTTP and WebSocket API on localhost, for tools that can only make web requests
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpSettings {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Port to listen on, on 127.0.0.1 only
+    #[serde(default = "default_http_port")]
+    pub port: u16,
+
+    /// Bearer token clients must send; any local program may connect when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+
+    /// Also serve Prometheus metrics on `/metrics`
+    #[serde(default)]
+    pub metrics: bool,
+}
+
+pub const DEFAULT_HTTP_PORT: u16 = 8765;
+
+fn default_http_port() -> u16 {
+    DEFAULT_HTTP_PORT
+}
+
+impl HttpSettings {
+    /// A random token, handed out when the API is turned on
+    pub fn generate_token() -> Result<String> {
+        let mut bytes = [0u8; 16];
+        File::open("/dev/urandom")
+            .and_then(|mut random| random.read_exact(&mut bytes))
+            .context("Failed to generate an HTTP token")?;
+        Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_HTTP_PORT,
+            token: None,
+            metrics: false,
+        }
+    }
+}
+
+/// Bridge to an MQTT broker, announcing the desk to Home Assistant
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MqttSettings {
+    /// `mqtt://[user[:password]@]host[:port]`; the bridge is off while unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub broker: Option<String>,
+
+    /// Prefix of the desk's own topics, e.g. `desk-control/height`
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+
+    /// Prefix Home Assistant watches for discovery payloads
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+}
+
+pub const DEFAULT_TOPIC_PREFIX: &str = "desk-control";
+pub const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+fn default_topic_prefix() -> String {
+    DEFAULT_TOPIC_PREFIX.to_string()
+}
+
+fn default_discovery_prefix() -> String {
+    DEFAULT_DISCOVERY_PREFIX.to_string()
+}
+
+/// Commands to run per desk event, e.g. `"posture_changed": ["~/bin/lights $DESK_POSTURE"]`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HookSettings {
+    /// Seconds a command may run before it is killed
+    #[serde(default = "default_hook_timeout", skip_serializing_if = "is_default_hook_timeout")]
+    pub timeout_secs: u64,
+
+    /// Commands run with `sh -c`, in order, for each event
+    #[serde(flatten)]
+    pub commands: BTreeMap<HookEvent, Vec<String>>,
+}
+
+pub const DEFAULT_HOOK_TIMEOUT_SECS: u64 = 30;
+
+fn default_hook_timeout() -> u64 {
+    DEFAULT_HOOK_TIMEOUT_SECS
+}
+
+fn is_default_hook_timeout(secs: &u64) -> bool {
+    *secs == DEFAULT_HOOK_TIMEOUT_SECS
+}
+
+impl Default for HookSettings {
+    fn default() -> Self {
+        Self {
+            timeout_secs: DEFAULT_HOOK_TIMEOUT_SECS,
+            commands: BTreeMap::new(),
+        }
+    }
+}
+
+impl HookSettings {
+    /// Commands configured for `event`
+    pub fn commands_for(&self, event: HookEvent) -> &[String] {
+        self.commands.get(&event).map_or(&[], Vec::as_slice)
+    }
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            broker: None,
+            topic_prefix: default_topic_prefix(),
+            discovery_prefix: default_discovery_prefix(),
+        }
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             desk_address: None,
-            presets: HeightPresets {
-                short: 650,   // 65.0 cm - typical sitting height
-                tall: 850,    // 85.0 cm - mid-level
-                grande: 1050, // 105.0 cm - standing height
-                venti: 1250,  // 125.0 cm - maximum height
-            },
+            display_unit: DisplayUnit::default(),
+            active_profile: default_profile_name(),
+            auto_select_profile: false,
+            profiles: BTreeMap::from([(default_profile_name(), Profile::default())]),
+            calibration: None,
+            desk_range: None,
+            http: HttpSettings::default(),
+            mqtt: MqttSettings::default(),
+            hooks: HookSettings::default(),
+            legacy_presets: None,
+            session_profile: None,
         }
     }
 }
 
+fn default_profile_name() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
 impl Config {
     /// Get the configuration directory path (~/.desk-control)
     pub fn config_dir() -> Result<PathBuf> {
@@ -55,67 +380,532 @@ impl Config {
         Ok(Self::config_dir()?.join("config"))
     }
 
+    /// Get the lock file path (~/.desk-control/config.lock)
+    pub fn lock_file() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("config.lock"))
+    }
+
     /// Load configuration from file, or create default if not exists
     pub fn load() -> Result<Self> {
         let config_file = Self::config_file()?;
 
         if config_file.exists() {
-            let content = fs::read_to_string(&config_file)
-                .context("Failed to read config file")?;
-            let config: Config = serde_json::from_str(&content)
-                .context("Failed to parse config file")?;
-            Ok(config)
+            Self::read_from(&config_file)
         } else {
             log::info!("Config file not found, creating default");
+            let _lock = ConfigLock::acquire()?;
+
+            // Another process may have created it while we waited for the lock
+            if config_file.exists() {
+                return Self::read_from(&config_file);
+            }
+
             let config = Config::default();
-            config.save()?;
+            config.write_to(&config_file)?;
             Ok(config)
         }
     }
 
-    /// Save configuration to file
-    pub fn save(&self) -> Result<()> {
-        let config_dir = Self::config_dir()?;
+    /// Read the config file if there is one, without creating it
+    pub fn read_existing() -> Result<Option<Self>> {
+        let config_file = Self::config_file()?;
+        if !config_file.exists() {
+            return Ok(None);
+        }
+        Self::read_from(&config_file).map(Some)
+    }
+
+    /// Send the config to `changes` whenever another process rewrites the file
+    ///
+    /// Polls the file's modification time; invalid edits are logged and
+    /// skipped. Returns once the receiver is dropped.
+    pub async fn watch(changes: UnboundedSender<Config>) {
+        let modified = || {
+            Self::config_file()
+                .and_then(|path| Ok(fs::metadata(path)?.modified()?))
+                .ok()
+        };
+        let mut last_modified = modified();
+
+        while !changes.is_closed() {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+
+            let current = modified();
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            match Self::read_existing() {
+                Ok(Some(config)) => {
+                    log::info!("Config file changed, reloading");
+                    let _ = changes.send(config);
+                }
+                Ok(None) => {}
+                Err(e) => log::warn!("Ignoring invalid config file change: {:#}", e),
+            }
+        }
+    }
+
+    /// Check settings that parse fine but make no sense together
+    pub fn validate(&self) -> Result<()> {
+        if let Some(address) = &self.desk_address {
+            if address.trim().is_empty() {
+                return Err(anyhow!("desk_address is empty"));
+            }
+        }
+
+        if let Some(range) = self.desk_range {
+            if range.min >= range.max {
+                return Err(anyhow!(
+                    "desk_range: minimum height {} is not below the maximum {}",
+                    range.min, range.max
+                ));
+            }
+        }
+
+        if self.http.port == 0 {
+            return Err(anyhow!("http.port must be between 1 and 65535"));
+        }
+        if self.http.token.as_deref().is_some_and(|token| token.trim().is_empty()) {
+            return Err(anyhow!("http.token is empty (unset it to turn off the check)"));
+        }
+
+        if let Some(broker) = &self.mqtt.broker {
+            crate::daemon::mqtt::Broker::parse(broker).context("mqtt.broker")?;
+        }
+        for (key, prefix) in [
+            ("mqtt.topic_prefix", &self.mqtt.topic_prefix),
+            ("mqtt.discovery_prefix", &self.mqtt.discovery_prefix),
+        ] {
+            if prefix.trim_matches('/').is_empty() || prefix.contains(['+', '#']) {
+                return Err(anyhow!("{} must be a topic without wildcards, not '{}'", key, prefix));
+            }
+        }
+
+        if self.hooks.timeout_secs == 0 {
+            return Err(anyhow!("hooks.timeout_secs must be at least 1"));
+        }
+        for (event, commands) in &self.hooks.commands {
+            if commands.iter().any(|command| command.trim().is_empty()) {
+                return Err(anyhow!("hooks.{} has an empty command", event.name()));
+            }
+        }
+
+        for (name, profile) in &self.profiles {
+            let limits = profile.limits;
+            if let (Some(min), Some(max)) = (limits.min, limits.max) {
+                if min > max {
+                    return Err(anyhow!(
+                        "Profile '{}': minimum height {} is above the maximum {}",
+                        name, min, max
+                    ));
+                }
+            }
+
+            for preset in DrinkSize::all() {
+                limits.check(profile.presets.get(preset)).with_context(|| {
+                    format!("Profile '{}': {} preset is outside the limits", name, preset.name())
+                })?;
+            }
+
+            if profile.reminders.interval_minutes == 0 {
+                return Err(anyhow!("Profile '{}': reminder interval must be at least 1 minute", name));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Atomically read, modify and save the configuration on disk
+    ///
+    /// The config file is re-read while holding the cross-process lock, so
+    /// two instances (e.g. the tray and a script) never overwrite each
+    /// other's changes. Returns the configuration as saved.
+    pub fn update<F>(modify: F) -> Result<Self>
+    where
+        F: FnOnce(&mut Config),
+    {
+        Self::try_update(|config| {
+            modify(config);
+            Ok(())
+        })
+    }
+
+    /// Like [`Config::update`], but nothing is written if `modify` fails
+    pub fn try_update<F>(modify: F) -> Result<Self>
+    where
+        F: FnOnce(&mut Config) -> Result<()>,
+    {
+        let _lock = ConfigLock::acquire()?;
+        let config_file = Self::config_file()?;
+
+        let mut config = if config_file.exists() {
+            Self::read_from(&config_file)?
+        } else {
+            Config::default()
+        };
+
+        modify(&mut config)?;
+        config.write_to(&config_file)?;
+        Ok(config)
+    }
+
+    /// List existing backups as (index, path), newest first
+    pub fn backups() -> Result<Vec<(usize, PathBuf)>> {
+        let config_file = Self::config_file()?;
+        Ok((1..=MAX_BACKUPS)
+            .map(|index| (index, backup_path(&config_file, index)))
+            .filter(|(_, path)| path.exists())
+            .collect())
+    }
+
+    /// Restore the configuration from backup number `index` (1 = newest)
+    ///
+    /// The current configuration is rotated into the backups first, so a
+    /// restore can itself be undone.
+    pub fn restore_backup(index: usize) -> Result<Self> {
+        let _lock = ConfigLock::acquire()?;
+        let config_file = Self::config_file()?;
+        let backup = backup_path(&config_file, index);
+
+        if !backup.exists() {
+            return Err(anyhow!("Backup {} does not exist ({:?})", index, backup));
+        }
+
+        let config = Self::read_from(&backup)
+            .with_context(|| format!("Backup {} is not a valid config", index))?;
+        config.write_to(&config_file)?;
+
+        log::info!("Configuration restored from {:?}", backup);
+        Ok(config)
+    }
+
+    /// Read and parse a config file
+    fn read_from(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .context("Failed to read config file")?;
+        let mut config: Config = serde_json::from_str(&content)
+            .context("Failed to parse config file")?;
+        config.migrate();
+        Ok(config)
+    }
+
+    /// Move pre-profile presets into the default profile and make sure the
+    /// active profile exists
+    fn migrate(&mut self) {
+        if let Some(presets) = self.legacy_presets.take() {
+            if self.profiles.is_empty() {
+                log::info!("Migrating presets into profile '{}'", DEFAULT_PROFILE);
+                self.profiles.insert(
+                    default_profile_name(),
+                    Profile {
+                        presets,
+                        ..Profile::default()
+                    },
+                );
+            }
+        }
+
+        if self.profiles.is_empty() {
+            self.profiles.insert(default_profile_name(), Profile::default());
+        }
+
+        if !self.profiles.contains_key(&self.active_profile) {
+            let fallback = self.profiles.keys().next().cloned().unwrap_or_default();
+            log::warn!(
+                "Active profile '{}' does not exist, using '{}'",
+                self.active_profile,
+                fallback
+            );
+            self.active_profile = fallback;
+        }
+    }
+
+    /// Choose the profile for this process without saving it
+    ///
+    /// An explicitly requested profile must exist. Otherwise, when
+    /// `auto_select_profile` is on, a profile named after the OS user wins.
+    pub fn select_session_profile(&mut self, requested: Option<&str>) -> Result<()> {
+        if let Some(name) = requested {
+            if !self.profiles.contains_key(name) {
+                return Err(anyhow!(
+                    "Unknown profile '{}' (available: {})",
+                    name,
+                    self.profile_names().join(", ")
+                ));
+            }
+            self.session_profile = Some(name.to_string());
+        } else if self.auto_select_profile {
+            if let Some(user) = os_username().filter(|u| self.profiles.contains_key(u)) {
+                log::info!("Auto-selected profile '{}' for OS user", user);
+                self.session_profile = Some(user);
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the session profile of `previous` (used after re-reading from disk)
+    pub fn inherit_session_profile(&mut self, previous: &Config) {
+        self.session_profile = previous
+            .session_profile
+            .clone()
+            .filter(|name| self.profiles.contains_key(name));
+    }
+
+    /// Name of the profile in use
+    pub fn active_profile_name(&self) -> &str {
+        self.session_profile.as_deref().unwrap_or(&self.active_profile)
+    }
+
+    /// Names of all profiles, sorted
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    /// The profile in use
+    ///
+    /// One removed since it was selected reads as the default settings, the
+    /// same ones [`Config::profile_mut`] recreates it with.
+    pub fn profile(&self) -> &Profile {
+        static DEFAULTS: OnceLock<Profile> = OnceLock::new();
+        self.profiles
+            .get(self.active_profile_name())
+            .unwrap_or_else(|| DEFAULTS.get_or_init(Profile::default))
+    }
+
+    /// The profile in use, mutably, created with the default settings if missing
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        let name = self.active_profile_name().to_string();
+        self.profiles.entry(name).or_default()
+    }
+
+    /// Write the config to `path` without ever leaving a truncated file behind
+    ///
+    /// The new content goes to a temporary file in the same directory, is
+    /// flushed to disk and then renamed over the old file. The previous
+    /// version is kept as `<path>.bak.1`, shifting older backups up.
+    /// Callers must hold the [`ConfigLock`].
+    fn write_to(&self, path: &Path) -> Result<()> {
+        let config_dir = path
+            .parent()
+            .context("Config file has no parent directory")?;
 
         // Create directory if it doesn't exist
         if !config_dir.exists() {
-            fs::create_dir_all(&config_dir)
+            fs::create_dir_all(config_dir)
                 .context("Failed to create config directory")?;
         }
 
-        let config_file = Self::config_file()?;
         let content = serde_json::to_string_pretty(self)
             .context("Failed to serialize config")?;
 
-        fs::write(&config_file, content)
-            .context("Failed to write config file")?;
+        let tmp_path = path.with_extension(format!("tmp.{}", std::process::id()));
+        let result = (|| {
+            let mut tmp = File::create(&tmp_path)
+                .context("Failed to create temporary config file")?;
+            tmp.write_all(content.as_bytes())
+                .context("Failed to write temporary config file")?;
+            tmp.sync_all()
+                .context("Failed to flush temporary config file")?;
+
+            if path.exists() {
+                rotate_backups(path)?;
+            }
 
-        log::info!("Configuration saved to {:?}", config_file);
+            fs::rename(&tmp_path, path)
+                .context("Failed to replace config file")
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result?;
+
+        // Persist the rename itself; not all platforms allow opening a directory
+        if let Ok(dir) = File::open(config_dir) {
+            let _ = dir.sync_all();
+        }
+
+        log::info!("Configuration saved to {:?}", path);
         Ok(())
     }
 
     /// Get height for a specific preset
-    pub fn get_preset(&self, preset: DrinkSize) -> u16 {
-        match preset {
-            DrinkSize::Short => self.presets.short,
-            DrinkSize::Tall => self.presets.tall,
-            DrinkSize::Grande => self.presets.grande,
-            DrinkSize::Venti => self.presets.venti,
+    pub fn get_preset(&self, preset: DrinkSize) -> Height {
+        self.profile().presets.get(preset)
+    }
+
+    /// Persist `height` as `preset` in this session's profile
+    ///
+    /// Goes through [`Config::update`], so other changes on disk are kept.
+    /// Returns the saved configuration with this session's profile selected.
+    pub fn save_preset(&self, preset: DrinkSize, height: Height) -> Result<Self> {
+        Self::update(|config| {
+            config.inherit_session_profile(self);
+            config.set_preset(preset, height);
+        })
+    }
+
+    /// Configure the desk at `address`, forgetting another desk's calibration and range
+    pub fn set_desk_address(&mut self, address: &str) {
+        if self.desk_address.as_deref() != Some(address) {
+            self.calibration = None;
+            self.desk_range = None;
+        }
+        self.desk_address = Some(address.to_string());
+    }
+
+    /// Calibration for the configured desk, or none if it was never calibrated
+    pub fn calibration(&self) -> Calibration {
+        self.calibration.unwrap_or_default()
+    }
+
+    /// Switch to a new calibration, keeping presets and limits where they are
+    ///
+    /// Heights in the config are real heights under the old calibration, so
+    /// they are moved along with it to still point at the same desk positions.
+    pub fn recalibrate(&mut self, calibration: Option<Calibration>) {
+        let old = self.calibration();
+        let new = calibration.unwrap_or_default();
+        let convert = |height: Height| new.to_real(old.to_reported(height));
+
+        for profile in self.profiles.values_mut() {
+            for preset in DrinkSize::all() {
+                let height = profile.presets.get_mut(preset);
+                *height = convert(*height);
+            }
+            profile.limits.min = profile.limits.min.map(convert);
+            profile.limits.max = profile.limits.max.map(convert);
+        }
+        self.desk_range = self.desk_range.map(|range| DeskRange {
+            min: convert(range.min),
+            max: convert(range.max),
+        });
+        self.calibration = calibration;
+    }
+
+    /// Record the desk's physical range and fit untouched default presets into it
+    ///
+    /// Returns the names of the profiles whose presets were adjusted.
+    pub fn set_desk_range(&mut self, range: DeskRange) -> Vec<String> {
+        self.desk_range = Some(range);
+
+        let defaults = HeightPresets::default();
+        let fitted = HeightPresets::defaults_within(range);
+        if fitted == defaults {
+            return Vec::new();
+        }
+
+        self.profiles
+            .iter_mut()
+            .filter(|(_, profile)| profile.presets == defaults)
+            .map(|(name, profile)| {
+                profile.presets = fitted.clone();
+                name.clone()
+            })
+            .collect()
+    }
+
+    /// Limits for moves: the profile's limits, narrowed to what the desk can reach
+    pub fn move_limits(&self) -> HeightLimits {
+        let limits = self.profile().limits;
+        let Some(range) = self.desk_range else {
+            return limits;
+        };
+
+        HeightLimits {
+            min: Some(limits.min.map_or(range.min, |min| min.max(range.min))),
+            max: Some(limits.max.map_or(range.max, |max| max.min(range.max))),
         }
     }
 
+    /// Format a height using the configured display unit
+    pub fn format_height(&self, height: Height) -> String {
+        self.display_unit.format(height)
+    }
+
     /// Set height for a specific preset
-    pub fn set_preset(&mut self, preset: DrinkSize, height_mm: u16) {
-        match preset {
-            DrinkSize::Short => self.presets.short = height_mm,
-            DrinkSize::Tall => self.presets.tall = height_mm,
-            DrinkSize::Grande => self.presets.grande = height_mm,
-            DrinkSize::Venti => self.presets.venti = height_mm,
+    pub fn set_preset(&mut self, preset: DrinkSize, height: Height) {
+        *self.profile_mut().presets.get_mut(preset) = height;
+    }
+}
+
+/// Name of the logged-in OS user
+pub fn os_username() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/// Exclusive advisory lock on the config directory, released on drop
+///
+/// Guards read-modify-write cycles across processes. Readers don't need it
+/// because the config file is only ever replaced atomically.
+pub struct ConfigLock {
+    file: File,
+}
+
+impl ConfigLock {
+    /// Block until the lock on ~/.desk-control/config.lock is acquired
+    pub fn acquire() -> Result<Self> {
+        Self::acquire_at(&Config::lock_file()?)
+    }
+
+    fn acquire_at(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Failed to create config directory")?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .context("Failed to open config lock file")?;
+
+        if file.try_lock_exclusive().is_err() {
+            log::info!("Config is locked by another process, waiting...");
+            file.lock_exclusive().context("Failed to lock config file")?;
         }
+
+        Ok(Self { file })
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Drop for ConfigLock {
+    fn drop(&mut self) {
+        // fs2's, not the std method of the same name (Rust 1.89+)
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// Path of backup number `index` for `config_file`
+fn backup_path(config_file: &Path, index: usize) -> PathBuf {
+    let mut name = config_file.as_os_str().to_owned();
+    name.push(format!(".bak.{}", index));
+    PathBuf::from(name)
+}
+
+/// Shift config.bak.N -> config.bak.N+1 (dropping the oldest) and copy the
+/// current config to config.bak.1
+fn rotate_backups(config_file: &Path) -> Result<()> {
+    for index in (1..MAX_BACKUPS).rev() {
+        let from = backup_path(config_file, index);
+        if from.exists() {
+            fs::rename(&from, backup_path(config_file, index + 1))
+                .context("Failed to rotate config backups")?;
+        }
+    }
+
+    fs::copy(config_file, backup_path(config_file, 1))
+        .context("Failed to back up config file")?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum DrinkSize {
     Short,
     Tall,
@@ -128,7 +918,7 @@ impl DrinkSize {
         vec![Self::Short, Self::Tall, Self::Grande, Self::Venti]
     }
 
-    pub fn name(&self) -> &str {
+    pub fn name(&self) -> &'static str {
         match self {
             Self::Short => "Short",
             Self::Tall => "Tall",
@@ -136,4 +926,227 @@ impl DrinkSize {
             Self::Venti => "Venti",
         }
     }
+
+    /// Look up a preset by name, ignoring case ("grande", "Grande")
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all()
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+/// How the desk is being used, judged from its height
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Posture {
+    Sitting,
+    Standing,
+}
+
+impl Posture {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sitting => "sitting",
+            Self::Standing => "standing",
+        }
+    }
+}
+
+/// Something the desk did that hooks can run commands for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookEvent {
+    Connected,
+    Disconnected,
+    MoveStarted,
+    MoveFinished,
+    MoveFailed,
+    PostureChanged,
+    PresetSelected,
+}
+
+impl HookEvent {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::Connected,
+            Self::Disconnected,
+            Self::MoveStarted,
+            Self::MoveFinished,
+            Self::MoveFailed,
+            Self::PostureChanged,
+            Self::PresetSelected,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Connected => "connected",
+            Self::Disconnected => "disconnected",
+            Self::MoveStarted => "move_started",
+            Self::MoveFinished => "move_finished",
+            Self::MoveFailed => "move_failed",
+            Self::PostureChanged => "posture_changed",
+            Self::PresetSelected => "preset_selected",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|event| event.name() == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config_file() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("config");
+        (dir, file)
+    }
+
+    #[test]
+    fn test_write_creates_file_without_leftovers() {
+        let (dir, file) = temp_config_file();
+        Config::default().write_to(&file).unwrap();
+
+        let loaded = Config::read_from(&file).unwrap();
+        assert_eq!(loaded.get_preset(DrinkSize::Grande), Height::from_units(10500));
+
+        let entries: Vec<_> = fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1, "temporary file or backup left behind");
+    }
+
+    #[test]
+    fn test_backups_rotate_and_cap() {
+        let (_dir, file) = temp_config_file();
+
+        for i in 0..(MAX_BACKUPS + 3) {
+            let mut config = Config::default();
+            config.set_preset(DrinkSize::Short, Height::from_mm(600 + i as u16).unwrap());
+            config.write_to(&file).unwrap();
+        }
+
+        let last = 600 + (MAX_BACKUPS + 2) as u16;
+        let short_mm = |config: Config| config.get_preset(DrinkSize::Short).as_mm() as u16;
+        assert_eq!(short_mm(Config::read_from(&file).unwrap()), last);
+
+        for index in 1..=MAX_BACKUPS {
+            let backup = Config::read_from(&backup_path(&file, index)).unwrap();
+            assert_eq!(short_mm(backup), last - index as u16);
+        }
+        assert!(!backup_path(&file, MAX_BACKUPS + 1).exists());
+    }
+
+    #[test]
+    fn test_lock_is_exclusive_and_released_on_drop() {
+        let (_dir, file) = temp_config_file();
+        let lock_path = file.with_extension("lock");
+
+        let lock = ConfigLock::acquire_at(&lock_path).unwrap();
+        let other = File::open(&lock_path).unwrap();
+        assert!(other.try_lock_exclusive().is_err());
+
+        drop(lock);
+        assert!(other.try_lock_exclusive().is_ok());
+    }
+
+    #[test]
+    fn test_migrates_legacy_presets_into_default_profile() {
+        let (_dir, file) = temp_config_file();
+        fs::write(
+            &file,
+            r#"{"desk_address": null, "presets": {"short": 700, "tall": 900, "grande": 1100, "venti": 1200}}"#,
+        )
+        .unwrap();
+
+        let config = Config::read_from(&file).unwrap();
+        assert_eq!(config.active_profile_name(), DEFAULT_PROFILE);
+        assert_eq!(config.get_preset(DrinkSize::Short), Height::from_mm(700).unwrap());
+
+        config.write_to(&file).unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+        assert!(saved.get("presets").is_none());
+        assert_eq!(saved["profiles"][DEFAULT_PROFILE]["presets"]["short"], 700);
+    }
+
+    #[test]
+    fn test_session_profile_selection() {
+        let mut config = Config::default();
+        config.profiles.insert("alex".to_string(), Profile::default());
+        config.set_preset(DrinkSize::Venti, Height::from_mm(1200).unwrap());
+
+        assert!(config.select_session_profile(Some("nobody")).is_err());
+        config.select_session_profile(Some("alex")).unwrap();
+        assert_eq!(config.active_profile_name(), "alex");
+        assert_eq!(config.active_profile, DEFAULT_PROFILE);
+        assert_eq!(config.get_preset(DrinkSize::Venti), Height::from_mm(1250).unwrap());
+
+        let mut reloaded = Config::default();
+        reloaded.inherit_session_profile(&config);
+        assert_eq!(reloaded.active_profile_name(), DEFAULT_PROFILE);
+
+        // A profile removed while selected reads as, and comes back with, the defaults
+        config.profiles.remove("alex");
+        assert_eq!(config.profile().presets, HeightPresets::default());
+        config.profile_mut().limits.max = Height::from_mm(1100);
+        assert_eq!(config.profiles["alex"].presets, HeightPresets::default());
+    }
+
+    #[test]
+    fn test_height_limits() {
+        let limits = HeightLimits {
+            min: Height::from_mm(700),
+            max: Height::from_mm(1200),
+        };
+        assert!(limits.check(Height::from_mm(1000).unwrap()).is_ok());
+        assert!(limits.check(Height::from_mm(650).unwrap()).is_err());
+        assert!(limits.check(Height::from_mm(1250).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_preset_from_name() {
+        assert_eq!(DrinkSize::from_name("grande"), Some(DrinkSize::Grande));
+        assert_eq!(DrinkSize::from_name(" VENTI "), Some(DrinkSize::Venti));
+        assert_eq!(DrinkSize::from_name("trenta"), None);
+    }
+
+    #[test]
+    fn test_recalibrate_keeps_desk_positions() {
+        let mut config = Config::default();
+        config.profile_mut().limits.max = Height::from_mm(1200);
+
+        // The desk reports 0 at 620mm: presets saved from its readings move up
+        config.recalibrate(Some(Calibration::from_point(Height::default(), Height::from_mm(620).unwrap())));
+        assert_eq!(config.get_preset(DrinkSize::Short), Height::from_mm(1270).unwrap());
+        assert_eq!(config.profile().limits.max, Height::from_mm(1820));
+
+        // Dropping the calibration restores the original heights
+        config.recalibrate(None);
+        assert_eq!(config.get_preset(DrinkSize::Short), Height::from_mm(650).unwrap());
+        assert_eq!(config.calibration, None);
+    }
+
+    #[test]
+    fn test_desk_range_fits_default_presets() {
+        let mm = |mm| Height::from_mm(mm).unwrap();
+        let mut config = Config::default();
+        config.profiles.insert("alex".to_string(), Profile::default());
+        config.profiles.get_mut("alex").unwrap().presets.venti = mm(1000);
+
+        // A short frame: only untouched default presets are squeezed in
+        let adjusted = config.set_desk_range(DeskRange { min: mm(640), max: mm(1050) });
+        assert_eq!(adjusted, vec![DEFAULT_PROFILE.to_string()]);
+        assert_eq!(config.get_preset(DrinkSize::Short), mm(650));
+        assert_eq!(config.get_preset(DrinkSize::Venti), mm(1050));
+        assert_eq!(config.profiles["alex"].presets.venti, mm(1000));
+
+        // Moves are limited to the range even without profile limits
+        assert_eq!(config.move_limits().max, Some(mm(1050)));
+
+        // A desk that reaches every default keeps them
+        let mut config = Config::default();
+        assert!(config.set_desk_range(DeskRange { min: mm(620), max: mm(1270) }).is_empty());
+        assert_eq!(config.profile().presets, HeightPresets::default());
+    }
 }
@@ -0,0 +1,149 @@
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::UnixStream;
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::protocol::{Direction, Request, Response, EVENT_METHOD};
+use super::service::Status;
+use crate::desk::watch::WatchEvent;
+use crate::desk::{DeskError, DeskResult, Height, MovementCommand};
+use crate::metrics::MoveSource;
+
+/// Talks to a running daemon over its socket
+///
+/// Every call uses a connection of its own, so a long move doesn't hold up
+/// a `stop` sent meanwhile.
+#[derive(Debug, Clone)]
+pub struct DaemonClient {
+    path: PathBuf,
+    /// Who this client's moves are counted for in the daemon's metrics
+    source: MoveSource,
+}
+
+impl DaemonClient {
+    /// The running daemon, or `None` if nothing answers on the socket
+    pub async fn find() -> Option<Self> {
+        let path = super::socket_path().ok()?;
+        UnixStream::connect(&path).await.ok()?;
+        Some(Self {
+            path,
+            source: MoveSource::Cli,
+        })
+    }
+
+    /// The same daemon, with moves counted for `source`
+    pub fn with_source(mut self, source: MoveSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Send one request and wait for its result
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> DeskResult<T> {
+        let mut lines = self.send(method, params).await?;
+
+        let line = lines
+            .next_line()
+            .await
+            .map_err(daemon_error)?
+            .ok_or_else(|| DeskError::Daemon("closed the connection without answering".to_string()))?;
+
+        let response: Response = serde_json::from_str(&line).map_err(daemon_error)?;
+        if let Some(error) = response.error {
+            return Err(error.into_desk_error());
+        }
+        serde_json::from_value(response.result.unwrap_or(Value::Null)).map_err(daemon_error)
+    }
+
+    /// Send every desk event to `events` until the daemon or the receiver goes away
+    ///
+    /// Starts with the current connection state and height.
+    pub async fn subscribe(&self, events: UnboundedSender<WatchEvent>) -> DeskResult<()> {
+        let mut lines = self.send("subscribe", Value::Null).await?;
+
+        loop {
+            let line = tokio::select! {
+                line = lines.next_line() => line.map_err(daemon_error)?,
+                _ = events.closed() => return Ok(()),
+            };
+            let Some(line) = line else {
+                return Err(DeskError::Daemon("stopped".to_string()));
+            };
+
+            let message: Value = serde_json::from_str(&line).map_err(daemon_error)?;
+            if message["method"] == EVENT_METHOD {
+                let event = serde_json::from_value(message["params"].clone()).map_err(daemon_error)?;
+                if events.send(event).is_err() {
+                    return Ok(());
+                }
+            } else if let Some(error) = message.get("error") {
+                return Err(DeskError::Daemon(error.to_string()));
+            }
+        }
+    }
+
+    /// Connect and write one request, returning the lines that come back
+    async fn send(&self, method: &str, params: Value) -> DeskResult<Lines<BufReader<OwnedReadHalf>>> {
+        let stream = UnixStream::connect(&self.path).await.map_err(daemon_error)?;
+        let (reader, mut writer) = stream.into_split();
+
+        let mut request = serde_json::to_string(&Request::new(1, method, params)).map_err(daemon_error)?;
+        request.push('\n');
+        writer.write_all(request.as_bytes()).await.map_err(daemon_error)?;
+
+        Ok(BufReader::new(reader).lines())
+    }
+
+    pub async fn status(&self) -> DeskResult<Status> {
+        self.call("status", Value::Null).await
+    }
+
+    pub async fn get_height(&self) -> DeskResult<Height> {
+        self.height_of("height", Value::Null).await
+    }
+
+    pub async fn get_raw_height(&self) -> DeskResult<Height> {
+        self.height_of("raw_height", Value::Null).await
+    }
+
+    /// Move to exactly `target`, returning once the desk got there
+    pub async fn move_to_height(&self, target: Height) -> DeskResult<()> {
+        let params = json!({ "height_mm": target, "source": self.source });
+        self.height_of("move", params).await.map(drop)
+    }
+
+    pub async fn move_for(&self, direction: MovementCommand, duration: Duration) -> DeskResult<()> {
+        let params = json!({
+            "direction": Direction::try_from(direction)?,
+            "seconds": duration.as_secs_f32(),
+            "source": self.source,
+        });
+        self.height_of("move_for", params).await.map(drop)
+    }
+
+    /// Send a single up or down command; repeat it to keep the desk moving
+    pub async fn hold(&self, direction: MovementCommand) -> DeskResult<()> {
+        self.call("hold", json!({ "direction": Direction::try_from(direction)? })).await
+    }
+
+    pub async fn move_to_end_stop(&self, direction: MovementCommand) -> DeskResult<Height> {
+        self.height_of("end_stop", json!({ "direction": Direction::try_from(direction)? })).await
+    }
+
+    pub async fn stop(&self) -> DeskResult<()> {
+        self.height_of("stop", Value::Null).await.map(drop)
+    }
+
+    /// Call a method whose result is `{"height_mm": ...}`
+    async fn height_of(&self, method: &str, params: Value) -> DeskResult<Height> {
+        let result: Value = self.call(method, params).await?;
+        serde_json::from_value(result["height_mm"].clone()).map_err(daemon_error)
+    }
+}
+
+fn daemon_error(error: impl std::fmt::Display) -> DeskError {
+    DeskError::Daemon(error.to_string())
+}
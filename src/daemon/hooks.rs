@@ -0,0 +1,278 @@
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::timeout;
+
+use super::service::DeskService;
+use crate::config::{Config, HookEvent, Posture};
+use crate::desk::watch::WatchEvent;
+use crate::desk::Height;
+
+/// A hook event with the environment variables its commands get
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trigger {
+    pub event: HookEvent,
+    pub env: Vec<(&'static str, String)>,
+}
+
+/// Turns the service's events into hook events
+///
+/// Remembers enough to report changes only: the service repeats
+/// `disconnected` for every failed retry, and heights arrive many times a second.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    connected: bool,
+    posture: Option<Posture>,
+    height: Option<Height>,
+}
+
+impl Tracker {
+    /// Hook events caused by `event`, if any
+    pub fn triggers(&mut self, event: &WatchEvent, config: &Config) -> Vec<Trigger> {
+        let mut triggers = Vec::new();
+        let mut trigger = |event, env: Vec<(&'static str, String)>| triggers.push(Trigger { event, env });
+
+        match event {
+            WatchEvent::Connecting { .. } => {}
+            WatchEvent::Connected { address } => {
+                self.connected = true;
+                trigger(HookEvent::Connected, vec![("DESK_ADDRESS", address.clone())]);
+            }
+            WatchEvent::Disconnected { error, retry_in_secs } => {
+                if std::mem::take(&mut self.connected) {
+                    trigger(
+                        HookEvent::Disconnected,
+                        vec![("DESK_ERROR", error.clone()), ("DESK_RETRY_IN_SECS", retry_in_secs.to_string())],
+                    );
+                }
+            }
+            WatchEvent::Height { height_mm, moving, .. } => {
+                self.height = Some(*height_mm);
+                // Judged at rest, so passing through on the way doesn't count
+                if !moving {
+                    let posture = config.profile().presets.posture(*height_mm);
+                    match self.posture.replace(posture) {
+                        Some(previous) if previous != posture => trigger(
+                            HookEvent::PostureChanged,
+                            vec![
+                                ("DESK_POSTURE", posture.name().to_string()),
+                                ("DESK_PREVIOUS_POSTURE", previous.name().to_string()),
+                            ],
+                        ),
+                        _ => {}
+                    }
+                }
+            }
+            WatchEvent::MoveStarted { target_mm, preset } => {
+                trigger(HookEvent::MoveStarted, target_env(config, *target_mm));
+                if let Some(preset) = preset {
+                    let mut env = target_env(config, *target_mm);
+                    env.push(("DESK_PRESET", preset.name().to_lowercase()));
+                    trigger(HookEvent::PresetSelected, env);
+                }
+            }
+            WatchEvent::MoveFinished { target_mm, height_mm } => {
+                self.height = Some(*height_mm);
+                trigger(HookEvent::MoveFinished, target_env(config, *target_mm));
+            }
+            WatchEvent::MoveFailed { target_mm, error } => {
+                let mut env = target_env(config, *target_mm);
+                env.push(("DESK_ERROR", error.clone()));
+                trigger(HookEvent::MoveFailed, env);
+            }
+        }
+
+        for trigger in &mut triggers {
+            trigger.env.push(("DESK_EVENT", trigger.event.name().to_string()));
+            trigger.env.push(("DESK_PROFILE", config.active_profile_name().to_string()));
+            if let Some(height) = self.height {
+                trigger.env.push(("DESK_HEIGHT_MM", height.as_mm().to_string()));
+                trigger.env.push(("DESK_HEIGHT", config.format_height(height)));
+            }
+        }
+        triggers
+    }
+}
+
+fn target_env(config: &Config, target: Height) -> Vec<(&'static str, String)> {
+    vec![
+        ("DESK_TARGET_MM", target.as_mm().to_string()),
+        ("DESK_TARGET", config.format_height(target)),
+    ]
+}
+
+/// Run the configured commands for every hook event until the service stops
+pub async fn run(service: Arc<DeskService>) {
+    let (current, mut events) = service.subscribe();
+    let mut tracker = Tracker::default();
+
+    // The state at startup isn't news, but later changes are measured against it
+    let config = service.config().await;
+    for event in &current {
+        tracker.triggers(event, &config);
+    }
+
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(missed)) => {
+                log::warn!("Hooks missed {} desk events", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let config = service.config().await;
+        for trigger in tracker.triggers(&event, &config) {
+            let commands = config.hooks.commands_for(trigger.event).to_vec();
+            if commands.is_empty() {
+                continue;
+            }
+            let limit = Duration::from_secs(config.hooks.timeout_secs);
+            tokio::spawn(async move {
+                for command in commands {
+                    run_command(&trigger, &command, limit).await;
+                }
+            });
+        }
+    }
+}
+
+/// Run `command` with `sh -c`, logging its output, and kill it after `limit`
+///
+/// The command gets a process group of its own, so that what it started in
+/// turn is killed along with it. Returns how it exited, or `None` if it
+/// couldn't start or ran out of time.
+async fn run_command(trigger: &Trigger, command: &str, limit: Duration) -> Option<ExitStatus> {
+    let name = trigger.event.name();
+    log::info!("Hook {}: running `{}`", name, command);
+
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(trigger.env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            log::warn!("Hook {}: could not start `{}`: {}", name, command, e);
+            return None;
+        }
+    };
+    log_output(name, child.stdout.take());
+    log_output(name, child.stderr.take());
+
+    match timeout(limit, child.wait()).await {
+        Ok(Ok(status)) => {
+            if !status.success() {
+                log::warn!("Hook {}: `{}` failed ({})", name, command, status);
+            }
+            Some(status)
+        }
+        Ok(Err(e)) => {
+            log::warn!("Hook {}: lost track of `{}`: {}", name, command, e);
+            None
+        }
+        Err(_) => {
+            log::warn!("Hook {}: `{}` took longer than {}s and was killed", name, command, limit.as_secs());
+            if let Some(pid) = child.id() {
+                // SAFETY: only sends a signal, to the group the command leads
+                unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) };
+            }
+            let _ = child.kill().await;
+            None
+        }
+    }
+}
+
+/// Log each line a command prints
+fn log_output(name: &'static str, output: Option<impl AsyncRead + Unpin + Send + 'static>) {
+    let Some(output) = output else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            log::info!("Hook {}: {}", name, line);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DrinkSize;
+
+    fn at(units: u16, moving: bool) -> WatchEvent {
+        WatchEvent::Height {
+            height_mm: Height::from_units(units),
+            speed: 0,
+            moving,
+        }
+    }
+
+    fn events(tracker: &mut Tracker, config: &Config, event: WatchEvent) -> Vec<HookEvent> {
+        tracker.triggers(&event, config).into_iter().map(|trigger| trigger.event).collect()
+    }
+
+    #[test]
+    fn test_reports_changes_only() {
+        let config = Config::default();
+        let mut tracker = Tracker::default();
+        let address = "AA:BB:CC:DD:EE:FF".to_string();
+
+        assert_eq!(events(&mut tracker, &config, WatchEvent::Connected { address }), [HookEvent::Connected]);
+        assert!(events(&mut tracker, &config, at(7000, false)).is_empty());
+
+        let grande = config.get_preset(DrinkSize::Grande);
+        let to_grande = WatchEvent::MoveStarted { target_mm: grande, preset: Some(DrinkSize::Grande) };
+        let started = tracker.triggers(&to_grande, &config);
+        assert_eq!(started[1].event, HookEvent::PresetSelected);
+        assert!(started[1].env.contains(&("DESK_PRESET", "grande".to_string())));
+        assert!(started[1].env.contains(&("DESK_HEIGHT_MM", "700".to_string())));
+        // Typing in the height of a preset isn't selecting it
+        let to_height = WatchEvent::MoveStarted { target_mm: grande, preset: None };
+        assert_eq!(events(&mut tracker, &config, to_height), [HookEvent::MoveStarted]);
+
+        // Passing the threshold while moving isn't a posture change yet
+        assert!(events(&mut tracker, &config, at(9500, true)).is_empty());
+        assert_eq!(events(&mut tracker, &config, at(10500, false)), [HookEvent::PostureChanged]);
+
+        let lost = WatchEvent::Disconnected { error: "gone".to_string(), retry_in_secs: 2 };
+        assert_eq!(events(&mut tracker, &config, lost.clone()), [HookEvent::Disconnected]);
+        assert!(events(&mut tracker, &config, lost).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_runs_commands_with_event_data() {
+        let trigger = Trigger {
+            event: HookEvent::PostureChanged,
+            env: vec![("DESK_POSTURE", "standing".to_string())],
+        };
+        let limit = Duration::from_secs(5);
+
+        let status = run_command(&trigger, "test \"$DESK_POSTURE\" = standing", limit).await;
+        assert!(status.unwrap().success());
+        assert_eq!(run_command(&trigger, "exit 3", limit).await.unwrap().code(), Some(3));
+
+        let started = std::time::Instant::now();
+        assert!(run_command(&trigger, "sleep 10", Duration::from_millis(100)).await.is_none());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // What the command started in the background goes too
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("survived");
+        let command = format!("(sleep 0.5; touch '{}') & sleep 10", marker.display());
+        assert!(run_command(&trigger, &command, Duration::from_millis(100)).await.is_none());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(!marker.exists());
+    }
+}
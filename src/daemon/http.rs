@@ -0,0 +1,417 @@
+use anyhow::{Context, Result};
+use axum::body::Bytes;
+use axum::extract::ws::rejection::WebSocketUpgradeRejection;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{DefaultBodyLimit, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, Method, StatusCode, Uri};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tower::Layer;
+
+use super::protocol::{RpcError, INVALID_PARAMS};
+use super::server;
+use super::service::DeskService;
+use crate::config::DrinkSize;
+use crate::desk::error::exit_code;
+use crate::metrics::{self, MoveSource};
+
+/// Bodies are small JSON objects
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Listen on `port` on the loopback interface only
+pub async fn bind(port: u16) -> Result<TcpListener> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Failed to listen on http://{}", address))
+}
+
+/// Serve the API to HTTP clients until the process exits
+pub async fn serve(service: Arc<DeskService>, listener: TcpListener) {
+    // The port clients must name in `Host`, even if the config changed since
+    let port = match listener.local_addr() {
+        Ok(address) => address.port(),
+        Err(e) => {
+            log::warn!("HTTP API unavailable: {}", e);
+            return;
+        }
+    };
+    let app = tower::util::MapRequestLayer::new(trim_trailing_slash).layer(router(service, port));
+    if let Err(e) = axum::serve(listener, axum::ServiceExt::<Request>::into_make_service(app)).await {
+        log::warn!("HTTP API stopped: {}", e);
+    }
+}
+
+#[derive(Clone)]
+struct Api {
+    service: Arc<DeskService>,
+    port: u16,
+}
+
+/// The API's routes, behind the local-only and token checks
+pub fn router(service: Arc<DeskService>, port: u16) -> Router {
+    let api = Api { service, port };
+    Router::new()
+        .route("/api/status", get(status))
+        .route("/api/height", get(height))
+        .route("/api/presets", get(presets))
+        .route("/api/presets/{name}", post(move_to_preset))
+        .route("/api/move", post(move_to))
+        .route("/api/stop", post(stop))
+        .route("/api/events", get(events))
+        .route("/metrics", get(metrics))
+        .fallback(|uri: Uri| async move { error(StatusCode::NOT_FOUND, format!("No such endpoint {}", uri.path())) })
+        .method_not_allowed_fallback(|method: Method, uri: Uri| async move {
+            error(
+                StatusCode::METHOD_NOT_ALLOWED,
+                format!("{} is not supported on {}", method, uri.path()),
+            )
+        })
+        .layer(middleware::from_fn_with_state(api.clone(), guard))
+        .layer(DefaultBodyLimit::max(MAX_BODY_BYTES))
+        .with_state(api)
+}
+
+/// Route `/api/presets/` like `/api/presets`
+fn trim_trailing_slash(mut request: Request) -> Request {
+    let path = request.uri().path();
+    if path.len() > 1 && path.ends_with('/') {
+        let trimmed = match request.uri().query() {
+            Some(query) => format!("{}?{}", path.trim_end_matches('/'), query),
+            None => path.trim_end_matches('/').to_string(),
+        };
+        if let Ok(uri) = trimmed.parse() {
+            *request.uri_mut() = uri;
+        }
+    }
+    request
+}
+
+/// Refuse web pages, requests without the token and POSTs that aren't JSON
+async fn guard(State(api): State<Api>, request: Request, next: Next) -> Response {
+    log::debug!("HTTP {} {}", request.method(), request.uri().path());
+    let headers = request.headers();
+
+    if !is_local(headers, api.port) {
+        log::info!("HTTP {} {} refused: not a local client", request.method(), request.uri().path());
+        return error(StatusCode::FORBIDDEN, "Only local programs may use the API, not web pages");
+    }
+
+    let token = api.service.config().await.http.token;
+    if !is_authorized(headers, request.uri(), token.as_deref()) {
+        let mut response = error(StatusCode::UNAUTHORIZED, "Missing or wrong bearer token");
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, header::HeaderValue::from_static("Bearer"));
+        return response;
+    }
+
+    if request.method() == Method::POST && !is_json(headers) {
+        return error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "POST requests need Content-Type: application/json",
+        );
+    }
+
+    next.run(request).await
+}
+
+fn header(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Whether the request carries `token`, as a bearer token or, for
+/// WebSocket clients which can't set headers, as `?token=`
+fn is_authorized(headers: &HeaderMap, uri: &Uri, token: Option<&str>) -> bool {
+    let Some(token) = token else {
+        return true;
+    };
+    let sent = match header(headers, header::AUTHORIZATION).and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(sent) => Some(sent.to_string()),
+        None => Query::<HashMap<String, String>>::try_from_uri(uri)
+            .ok()
+            .and_then(|Query(mut query)| query.remove("token")),
+    };
+    sent.is_some_and(|sent| same_token(sent.trim(), token))
+}
+
+/// Whether the request comes from a local program rather than a web page
+///
+/// Any page the user visits can send requests to localhost, and through DNS
+/// rebinding read the answers, so the API only takes requests that name
+/// 127.0.0.1 or localhost as their host and carry no `Origin`, which
+/// browsers add to cross-origin requests and WebSockets.
+fn is_local(headers: &HeaderMap, port: u16) -> bool {
+    if headers.contains_key(header::ORIGIN) {
+        return false;
+    }
+    let Some(host) = header(headers, header::HOST) else {
+        return false;
+    };
+    let (name, host_port) = match host.rsplit_once(':') {
+        Some((name, host_port)) => (name, host_port.parse().ok()),
+        None => (host, Some(80)),
+    };
+    matches!(name.to_ascii_lowercase().as_str(), "127.0.0.1" | "localhost") && host_port == Some(port)
+}
+
+/// Whether the body is declared as JSON, which a web page can't send
+/// cross-origin without the browser asking first
+fn is_json(headers: &HeaderMap) -> bool {
+    header(headers, header::CONTENT_TYPE)
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|kind| kind.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// Compare without stopping at the first difference, so the time taken
+/// doesn't give the token away
+fn same_token(sent: &str, token: &str) -> bool {
+    sent.len() == token.len() && sent.bytes().zip(token.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn error(status: StatusCode, message: impl std::fmt::Display) -> Response {
+    (status, Json(json!({ "error": message.to_string() }))).into_response()
+}
+
+async fn status(State(api): State<Api>) -> Response {
+    call(&api.service, "status", Value::Null).await
+}
+
+async fn height(State(api): State<Api>) -> Response {
+    call(&api.service, "height", Value::Null).await
+}
+
+/// The active profile's presets in millimeters
+async fn presets(State(api): State<Api>) -> Response {
+    let config = api.service.config().await;
+    let presets: Map<String, Value> = DrinkSize::all()
+        .into_iter()
+        .map(|preset| (preset.name().to_lowercase(), json!(config.get_preset(preset))))
+        .collect();
+    Json(json!({ "profile": config.active_profile_name(), "presets": presets })).into_response()
+}
+
+async fn move_to_preset(State(api): State<Api>, Path(name): Path<String>) -> Response {
+    match DrinkSize::from_name(&name) {
+        Some(preset) => {
+            let params = json!({ "target": preset.name(), "source": MoveSource::Http });
+            call(&api.service, "move", params).await
+        }
+        None => error(StatusCode::NOT_FOUND, format!("Unknown preset '{}'", name)),
+    }
+}
+
+/// Same params as the socket's `move`; an empty body is left for it to reject
+async fn move_to(State(api): State<Api>, body: Bytes) -> Response {
+    match serde_json::from_slice::<Value>(&body) {
+        Ok(mut params) => {
+            if let Some(params) = params.as_object_mut() {
+                params.insert("source".to_string(), json!(MoveSource::Http));
+            }
+            call(&api.service, "move", params).await
+        }
+        Err(e) => error(StatusCode::BAD_REQUEST, format!("Invalid JSON body: {}", e)),
+    }
+}
+
+async fn stop(State(api): State<Api>) -> Response {
+    call(&api.service, "stop", Value::Null).await
+}
+
+async fn metrics(State(api): State<Api>, uri: Uri) -> Response {
+    if !api.service.config().await.http.metrics {
+        return error(StatusCode::NOT_FOUND, format!("No such endpoint {}", uri.path()));
+    }
+    // The Prometheus text format
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")], metrics::render()).into_response()
+}
+
+/// Run a socket method and turn its outcome into a response
+async fn call(service: &DeskService, method: &str, params: Value) -> Response {
+    // Only `subscribe` writes to the socket directly, and it isn't routed here
+    let (outgoing, _) = mpsc::unbounded_channel();
+    match server::dispatch(service, method, params, &outgoing).await {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => {
+            log::info!("HTTP {} failed: {}", method, e.message);
+            error_response(e)
+        }
+    }
+}
+
+/// Error body with the CLI exit code and friendly message, and a matching status
+fn error_response(error: RpcError) -> Response {
+    let mut body = Map::new();
+    body.insert("error".to_string(), json!(error.message));
+    if let Some(Value::Object(data)) = error.data {
+        body.extend(data);
+    }
+
+    let status = match body.get("exit_code").and_then(Value::as_i64) {
+        _ if error.code == INVALID_PARAMS => StatusCode::BAD_REQUEST,
+        Some(code) => match i32::try_from(code).unwrap_or(exit_code::FAILURE) {
+            exit_code::NOT_CONFIGURED | exit_code::NOT_FOUND | exit_code::BLUETOOTH | exit_code::CONNECTION => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            exit_code::TIMEOUT => StatusCode::GATEWAY_TIMEOUT,
+            exit_code::OBSTRUCTED => StatusCode::CONFLICT,
+            exit_code::OUT_OF_RANGE => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        },
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, Json(Value::Object(body))).into_response()
+}
+
+async fn events(State(api): State<Api>, upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>) -> Response {
+    match upgrade {
+        Ok(upgrade) => upgrade.on_upgrade(move |socket| stream_events(api.service, socket)),
+        Err(e) => error(e.status(), format!("/api/events is a WebSocket: {}", e.body_text())),
+    }
+}
+
+/// Push every desk event to a WebSocket as a text message
+///
+/// Starts with the current connection state and height, like `subscribe` on
+/// the socket.
+async fn stream_events(service: Arc<DeskService>, mut socket: WebSocket) {
+    let (current, mut events) = service.subscribe();
+    let message = |event| Message::Text(json!(event).to_string().into());
+    for event in current {
+        if socket.send(message(event)).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if socket.send(message(event)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => log::debug!("WebSocket client missed {} events", missed),
+                Err(RecvError::Closed) => {
+                    let _ = socket.send(Message::Close(None)).await;
+                    return;
+                }
+            },
+            // Reading answers pings; a close or an error ends the stream
+            received = socket.recv() => match received {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use tower::ServiceExt;
+
+    const HOST: &str = "127.0.0.1:8765";
+
+    /// Send a request through the same layers as `serve`, returning the status and JSON body
+    async fn send(service: &Arc<DeskService>, request: axum::http::request::Builder) -> (StatusCode, Value) {
+        let app = tower::util::MapRequestLayer::new(trim_trailing_slash).layer(router(Arc::clone(service), 8765));
+        let response = app.oneshot(request.body(axum::body::Body::empty()).unwrap()).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    fn get(uri: &str) -> axum::http::request::Builder {
+        Request::get(uri).header(header::HOST, HOST)
+    }
+
+    fn post(uri: &str) -> axum::http::request::Builder {
+        Request::post(uri)
+            .header(header::HOST, HOST)
+            .header(header::CONTENT_TYPE, "application/json")
+    }
+
+    #[test]
+    fn test_checks_tokens() {
+        let headers = |authorization: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, authorization.parse().unwrap());
+            headers
+        };
+        let uri: Uri = "/api/move".parse().unwrap();
+        assert!(is_authorized(&headers("Bearer s3cret"), &uri, Some("s3cret")));
+        assert!(!is_authorized(&headers("Bearer other"), &uri, Some("s3cret")));
+
+        let socket: Uri = "/api/events?token=s3%2Fcr+et".parse().unwrap();
+        assert!(is_authorized(&HeaderMap::new(), &socket, Some("s3/cr et")));
+        assert!(!is_authorized(&HeaderMap::new(), &socket, Some("s3/cr et!")));
+        assert!(is_authorized(&HeaderMap::new(), &uri, None));
+    }
+
+    #[tokio::test]
+    async fn test_refuses_web_pages() {
+        let host = |host: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::HOST, host.parse().unwrap());
+            headers
+        };
+        assert!(is_local(&host("127.0.0.1:8765"), 8765));
+        assert!(is_local(&host("LOCALHOST:8765"), 8765));
+        assert!(!is_local(&HeaderMap::new(), 8765));
+        assert!(!is_local(&host("localhost:8080"), 8765));
+        // A rebound name still says so in Host
+        assert!(!is_local(&host("evil.example:8765"), 8765));
+        let mut cross_origin = host("127.0.0.1:8765");
+        cross_origin.insert(header::ORIGIN, "https://evil.example".parse().unwrap());
+        assert!(!is_local(&cross_origin, 8765));
+
+        let service = DeskService::new(Config::default());
+        assert_eq!(send(&service, Request::get("/api/status")).await.0, StatusCode::FORBIDDEN);
+        let form = get("/api/stop").method(Method::POST).header(header::CONTENT_TYPE, "text/plain");
+        assert_eq!(send(&service, form).await.0, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let mut config = Config::default();
+        config.http.token = Some("s3cret".to_string());
+        let service = DeskService::new(config);
+        assert_eq!(send(&service, get("/api/status")).await.0, StatusCode::UNAUTHORIZED);
+        let authorized = get("/api/status").header(header::AUTHORIZATION, "Bearer s3cret");
+        assert_eq!(send(&service, authorized).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_routes_to_service_methods() {
+        let service = DeskService::new(Config::default());
+
+        let (status, body) = send(&service, get("/api/status")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["connected"], false);
+
+        let (_, presets) = send(&service, get("/api/presets/")).await;
+        assert_eq!(presets["presets"]["grande"], 1050);
+
+        // Without a desk the move fails, keeping the CLI's exit code
+        let (status, moved) = send(&service, post("/api/presets/grande")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(moved["exit_code"], exit_code::NOT_CONFIGURED);
+
+        assert_eq!(send(&service, post("/api/move")).await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(send(&service, post("/api/presets/huge")).await.0, StatusCode::NOT_FOUND);
+        let (status, body) = send(&service, get("/api/stop").method(Method::DELETE)).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(body["error"], "DELETE is not supported on /api/stop");
+        assert_eq!(send(&service, get("/api/events")).await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(send(&service, get("/metrics")).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(send(&service, get("/nowhere")).await.0, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,265 @@
+pub mod client;
+pub mod hooks;
+pub mod http;
+pub mod mqtt;
+pub mod protocol;
+pub mod server;
+pub mod service;
+
+pub use client::DaemonClient;
+pub use service::DeskService;
+
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::Config;
+use crate::desk::watch::{self, WatchEvent};
+use crate::desk::{DeskController, DeskError, DeskResult, Height, MovementCommand};
+use crate::instance::{self, InstanceLock};
+#[cfg(target_os = "linux")]
+use crate::systemd;
+
+/// Where the daemon listens: the user's runtime directory, or next to the config
+pub fn socket_path() -> Result<PathBuf> {
+    match dirs::runtime_dir() {
+        Some(dir) => Ok(dir.join("desk-control.sock")),
+        None => Ok(Config::config_dir()?.join("daemon.sock")),
+    }
+}
+
+/// Start the desk service and its socket on the current runtime
+///
+/// Returns `None` if another daemon is running or starting.
+pub async fn start(config: Config) -> Result<Option<Arc<DeskService>>> {
+    // Checking the socket alone lets two daemons starting at once both replace it
+    let Some(lock) = InstanceLock::try_acquire("daemon")? else {
+        return Ok(None);
+    };
+
+    let path = socket_path()?;
+    let listener = server::bind(&path).await?;
+    // Released when the process exits; a failed bind above released it already
+    std::mem::forget(lock);
+
+    let http = config.http.clone();
+    let service = DeskService::new(config);
+    tokio::spawn(Arc::clone(&service).run());
+    tokio::spawn(server::serve(Arc::clone(&service), listener));
+
+    // A busy port only costs the HTTP API, not the daemon
+    if http.enabled {
+        match http::bind(http.port).await {
+            Ok(listener) => {
+                log::info!("HTTP API listening on http://127.0.0.1:{}", http.port);
+                tokio::spawn(http::serve(Arc::clone(&service), listener));
+            }
+            Err(e) => log::warn!("HTTP API unavailable: {:#}", e),
+        }
+    }
+    tokio::spawn(mqtt::run(Arc::clone(&service)));
+    tokio::spawn(hooks::run(Arc::clone(&service)));
+
+    let (changes, mut changed) = mpsc::unbounded_channel();
+    tokio::spawn(Config::watch(changes));
+    let reloading = Arc::clone(&service);
+    tokio::spawn(async move {
+        while let Some(config) = changed.recv().await {
+            reloading.reload(config).await;
+        }
+    });
+
+    log::info!("Daemon listening on {}", path.display());
+    Ok(Some(service))
+}
+
+/// Whether this process hosts the daemon, for the host to exit along with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hosting {
+    No,
+    Running,
+    Stopped,
+}
+
+static HOSTING: LazyLock<tokio::sync::watch::Sender<Hosting>> =
+    LazyLock::new(|| tokio::sync::watch::Sender::new(Hosting::No));
+
+/// Whether the daemon hosted here stopped after Ctrl-C or SIGTERM, so the host should exit
+pub fn hosted_daemon_stopped() -> bool {
+    *HOSTING.borrow() == Hosting::Stopped
+}
+
+/// Wait until the daemon hosted here has stopped; never returns if there is none
+pub async fn wait_for_hosted_stop() {
+    let _ = HOSTING.subscribe().wait_for(|hosting| *hosting == Hosting::Stopped).await;
+}
+
+/// Give the daemon hosted here, if any, time to stop after Ctrl-C before the process exits
+pub async fn finish_hosting() {
+    if *HOSTING.borrow() == Hosting::Running {
+        let _ = tokio::time::timeout(Duration::from_secs(5), wait_for_hosted_stop()).await;
+    }
+}
+
+/// Connect to the running daemon, starting one in this process if there is none
+pub async fn connect_or_start(config: Config) -> Result<DaemonClient> {
+    if let Some(client) = DaemonClient::find().await {
+        log::info!("Using the running desk-control daemon");
+        #[cfg(target_os = "linux")]
+        tokio::spawn(systemd::supervise_client(client.clone()));
+        return Ok(client);
+    }
+
+    // Losing a race with another process starting a daemon is fine too
+    match start(config).await {
+        Ok(Some(service)) => {
+            #[cfg(target_os = "linux")]
+            tokio::spawn(systemd::supervise(Arc::clone(&service)));
+            // Hosting the daemon means cleaning up after it when asked to go
+            // away; the host then sees it stopped and exits in its own way
+            HOSTING.send_replace(Hosting::Running);
+            tokio::spawn(async move {
+                if shutdown_signal().await.is_ok() {
+                    stop(&service).await;
+                    HOSTING.send_replace(Hosting::Stopped);
+                }
+            });
+        }
+        Ok(None) => log::info!("Another desk-control daemon is starting"),
+        Err(e) => log::info!("Could not start a daemon here: {:#}", e),
+    }
+
+    // A daemon that won the race may still be opening its socket
+    for _ in 0..20 {
+        if let Some(client) = DaemonClient::find().await {
+            return Ok(client);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Err(anyhow!("Could not start or reach the desk-control daemon"))
+}
+
+/// `desk-control daemon`: serve clients until Ctrl-C or SIGTERM
+pub async fn run(config: Config) -> Result<()> {
+    let Some(service) = start(config).await? else {
+        eprintln!(
+            "A desk-control daemon is already running{}; commands use that one.",
+            instance::holder_suffix("daemon")
+        );
+        // Not a failure, so a unit with Restart=on-failure doesn't keep trying
+        // while the tray hosts the daemon
+        #[cfg(target_os = "linux")]
+        systemd::notify("READY=1\nSTATUS=Another desk-control daemon is already running");
+        return Ok(());
+    };
+    #[cfg(target_os = "linux")]
+    tokio::spawn(systemd::supervise(Arc::clone(&service)));
+    shutdown_signal().await?;
+    stop(&service).await;
+    Ok(())
+}
+
+/// Wait for Ctrl-C, or SIGTERM from `systemctl stop` or a logout
+async fn shutdown_signal() -> Result<()> {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result?,
+        _ = terminate.recv() => {}
+    }
+    Ok(())
+}
+
+/// Stop the desk, disconnect and remove the socket before the process exits
+async fn stop(service: &DeskService) {
+    #[cfg(target_os = "linux")]
+    systemd::notify("STOPPING=1");
+    log::info!("Shutting down");
+    let _ = tokio::time::timeout(Duration::from_secs(3), service.shutdown()).await;
+    if let Ok(path) = socket_path() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Send the desk's events to `events`: through the running daemon if there is
+/// one, otherwise over a Bluetooth link of this process's own
+///
+/// The returned task ends once the receiver of `events` is dropped.
+pub async fn follow(config: &Config, events: mpsc::UnboundedSender<WatchEvent>) -> DeskResult<JoinHandle<()>> {
+    let address = config.desk_address.clone().ok_or(DeskError::NotConfigured)?;
+    let watcher = match DaemonClient::find().await {
+        Some(client) => tokio::spawn(async move {
+            if let Err(e) = client.subscribe(events).await {
+                eprintln!("Error: {}", e);
+            }
+        }),
+        None => tokio::spawn(watch::watch(address, config.calibration(), events)),
+    };
+    Ok(watcher)
+}
+
+/// The desk as a client sees it: through the daemon if one is running,
+/// otherwise over a Bluetooth connection of its own
+pub enum Desk {
+    Direct(DeskController),
+    Daemon(DaemonClient),
+}
+
+impl Desk {
+    /// Use the running daemon, or connect to the configured desk directly
+    pub async fn connect(config: &Config) -> DeskResult<Self> {
+        if let Some(client) = DaemonClient::find().await {
+            return Ok(Self::Daemon(client));
+        }
+
+        let address = config.desk_address.clone().ok_or(DeskError::NotConfigured)?;
+        let desk = DeskController::connect(Some(address), config.calibration()).await?;
+        Ok(Self::Direct(desk))
+    }
+
+    pub async fn get_height(&self) -> DeskResult<Height> {
+        match self {
+            Self::Direct(desk) => desk.get_height().await,
+            Self::Daemon(client) => client.get_height().await,
+        }
+    }
+
+    /// Height as the desk reports it, ignoring the calibration
+    pub async fn get_raw_height(&self) -> DeskResult<Height> {
+        match self {
+            Self::Direct(desk) => desk.get_raw_height().await,
+            Self::Daemon(client) => client.get_raw_height().await,
+        }
+    }
+
+    /// Move to exactly `target`; limits are the caller's business
+    pub async fn move_to_height(&self, target: Height) -> DeskResult<()> {
+        match self {
+            Self::Direct(desk) => desk.move_to_height(target).await,
+            Self::Daemon(client) => client.move_to_height(target).await,
+        }
+    }
+
+    pub async fn move_for(&self, direction: MovementCommand, duration: Duration) -> DeskResult<()> {
+        match self {
+            Self::Direct(desk) => desk.move_for(direction, duration).await,
+            Self::Daemon(client) => client.move_for(direction, duration).await,
+        }
+    }
+
+    pub async fn move_to_end_stop(&self, direction: MovementCommand) -> DeskResult<Height> {
+        match self {
+            Self::Direct(desk) => desk.move_to_end_stop(direction).await,
+            Self::Daemon(client) => client.move_to_end_stop(direction).await,
+        }
+    }
+
+    pub async fn stop(&self) -> DeskResult<()> {
+        match self {
+            Self::Direct(desk) => desk.stop().await,
+            Self::Daemon(client) => client.stop().await,
+        }
+    }
+}
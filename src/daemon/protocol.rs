@@ -0,0 +1,230 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::desk::error::exit_code;
+use crate::desk::watch::WatchEvent;
+use crate::desk::{DeskError, MovementCommand};
+
+/// JSON-RPC version sent and expected in every message
+pub const VERSION: &str = "2.0";
+
+/// Method name of the notifications sent to subscribed clients
+pub const EVENT_METHOD: &str = "event";
+
+/// Standard JSON-RPC error codes
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// Error code for everything the desk or the config reports
+pub const DESK_ERROR: i64 = -32000;
+
+/// One line sent by a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub jsonrpc: String,
+    /// Missing for notifications, which get no response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+impl Request {
+    pub fn new(id: u64, method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: VERSION.to_string(),
+            id: Some(id.into()),
+            method: method.to_string(),
+            params,
+        }
+    }
+}
+
+/// The daemon's answer to a request, carrying either a result or an error
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    pub fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self {
+            jsonrpc: VERSION.to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// A desk event pushed to subscribed clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: WatchEvent,
+}
+
+impl Notification {
+    pub fn event(event: WatchEvent) -> Self {
+        Self {
+            jsonrpc: VERSION.to_string(),
+            method: EVENT_METHOD.to_string(),
+            params: event,
+        }
+    }
+}
+
+/// A failed request, with the CLI exit code and notification text in `data`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn invalid_params(error: impl std::fmt::Display) -> Self {
+        Self::new(INVALID_PARAMS, format!("Invalid params: {}", error))
+    }
+
+    /// Turn the error back into a `DeskError` so clients keep exit codes and messages
+    pub fn into_desk_error(self) -> DeskError {
+        let field = |name: &str| self.data.as_ref().and_then(|data| data.get(name).cloned());
+        let exit_code = field("exit_code")
+            .and_then(|code| code.as_i64())
+            .and_then(|code| i32::try_from(code).ok())
+            .unwrap_or(match self.code {
+                INVALID_PARAMS => exit_code::OUT_OF_RANGE,
+                _ => exit_code::FAILURE,
+            });
+        let user_message = field("user_message")
+            .and_then(|message| message.as_str().map(str::to_string))
+            .unwrap_or_else(|| self.message.clone());
+
+        DeskError::Remote {
+            message: self.message,
+            exit_code,
+            user_message,
+        }
+    }
+}
+
+impl From<DeskError> for RpcError {
+    fn from(error: DeskError) -> Self {
+        Self {
+            code: DESK_ERROR,
+            message: error.to_string(),
+            data: Some(json!({
+                "exit_code": error.exit_code(),
+                "user_message": error.user_message(),
+            })),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(error: anyhow::Error) -> Self {
+        Self {
+            code: DESK_ERROR,
+            message: format!("{:#}", error),
+            data: Some(json!({
+                "exit_code": crate::desk::error::exit_code_for(&error),
+                "user_message": crate::desk::error::user_message_for(&error),
+            })),
+        }
+    }
+}
+
+/// Which way the desk should travel, as written in params ("up" or "down")
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl From<Direction> for MovementCommand {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Up => MovementCommand::Up,
+            Direction::Down => MovementCommand::Down,
+        }
+    }
+}
+
+impl TryFrom<MovementCommand> for Direction {
+    type Error = DeskError;
+
+    fn try_from(command: MovementCommand) -> Result<Self, Self::Error> {
+        match command {
+            MovementCommand::Up => Ok(Direction::Up),
+            MovementCommand::Down => Ok(Direction::Down),
+            other => Err(DeskError::InvalidTarget(format!("{:?} is not a direction", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::desk::Height;
+
+    #[test]
+    fn test_parses_requests_and_notifications() {
+        let request: Request =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","id":7,"method":"move","params":{"target":"grande"}}"#)
+                .unwrap();
+        assert_eq!(request.id, Some(json!(7)));
+        assert_eq!(request.params["target"], "grande");
+
+        let notification: Request = serde_json::from_str(r#"{"jsonrpc":"2.0","method":"stop"}"#).unwrap();
+        assert_eq!(notification.id, None);
+        assert!(notification.params.is_null());
+
+        let event = Notification::event(WatchEvent::Height {
+            height_mm: Height::from_units(10500),
+            speed: 0,
+            moving: false,
+        });
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({"jsonrpc":"2.0","method":"event","params":{"event":"height","height_mm":1050,"speed":0,"moving":false}})
+        );
+    }
+
+    #[test]
+    fn test_errors_keep_exit_codes() {
+        let error = RpcError::from(DeskError::NotConfigured);
+        assert_eq!(error.code, DESK_ERROR);
+
+        let remote = error.into_desk_error();
+        assert_eq!(remote.exit_code(), exit_code::NOT_CONFIGURED);
+        assert_eq!(remote.user_message(), DeskError::NotConfigured.user_message());
+        assert_eq!(remote.to_string(), DeskError::NotConfigured.to_string());
+
+        let unknown = RpcError::new(METHOD_NOT_FOUND, "Unknown method 'jump'").into_desk_error();
+        assert_eq!(unknown.exit_code(), exit_code::FAILURE);
+    }
+}
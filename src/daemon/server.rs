@@ -0,0 +1,345 @@
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+use super::protocol::{
+    Direction, Notification, Request, Response, RpcError, INVALID_REQUEST, METHOD_NOT_FOUND,
+    PARSE_ERROR, VERSION,
+};
+use super::service::DeskService;
+use crate::config_keys::ConfigKey;
+use crate::desk::Height;
+use crate::metrics::MoveSource;
+use crate::movement::MoveRequest;
+
+/// Listen on `path`, replacing a socket left behind by a daemon that crashed
+///
+/// Fails if another daemon is still answering there. Only the owner can
+/// connect to the socket.
+pub async fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(anyhow!("Another desk-control daemon is already listening on {}", path.display()));
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to listen on {}", path.display()))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Accept clients forever, each on its own task
+pub async fn serve(service: Arc<DeskService>, listener: UnixListener) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(handle_client(Arc::clone(&service), stream));
+            }
+            Err(e) => {
+                log::warn!("Failed to accept a client: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+/// Answer one client's requests, one JSON message per line
+async fn handle_client(service: Arc<DeskService>, stream: UnixStream) {
+    let (reader, mut writer) = stream.into_split();
+
+    // Responses and event notifications share the connection, so all writes
+    // go through one task
+    let (outgoing, mut to_write) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        while let Some(mut line) = to_write.recv().await {
+            line.push('\n');
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // Requests run concurrently so a `stop` isn't stuck behind a `move`
+        let service = Arc::clone(&service);
+        let outgoing = outgoing.clone();
+        tokio::spawn(async move {
+            if let Some(response) = respond(&service, &line, &outgoing).await {
+                send(&outgoing, &response);
+            }
+        });
+    }
+}
+
+/// Handle one line; `None` for notifications, which get no response
+async fn respond(service: &DeskService, line: &str, outgoing: &UnboundedSender<String>) -> Option<Response> {
+    let message: Value = match serde_json::from_str(line) {
+        Ok(message) => message,
+        Err(e) => {
+            return Some(Response::new(
+                Value::Null,
+                Err(RpcError::new(PARSE_ERROR, format!("Parse error: {}", e))),
+            ))
+        }
+    };
+
+    let id = message.get("id").cloned().unwrap_or(Value::Null);
+    let request = match serde_json::from_value::<Request>(message) {
+        Ok(request) if request.jsonrpc == VERSION => request,
+        _ => {
+            return Some(Response::new(
+                id,
+                Err(RpcError::new(INVALID_REQUEST, "Invalid request")),
+            ))
+        }
+    };
+
+    log::debug!("Request: {} {}", request.method, request.params);
+    let outcome = dispatch(service, &request.method, request.params, outgoing).await;
+    if let Err(e) = &outcome {
+        log::info!("{} failed: {}", request.method, e.message);
+    }
+    request.id.map(|id| Response::new(id, outcome))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MoveParams {
+    /// Preset, height or signed change, as on the command line
+    target: Option<String>,
+    /// Exact height, checked against the profile's limits
+    height_mm: Option<Height>,
+    /// Profile used to resolve and check the move instead of the daemon's
+    profile: Option<String>,
+    /// Who asked, for the metrics
+    source: Option<MoveSource>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MoveForParams {
+    direction: Direction,
+    #[serde(default = "default_seconds")]
+    seconds: f32,
+    source: Option<MoveSource>,
+}
+
+fn default_seconds() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DirectionParams {
+    direction: Direction,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyParams {
+    key: String,
+    #[serde(default)]
+    value: Option<String>,
+}
+
+/// Call the service method named by `method`
+pub async fn dispatch(
+    service: &DeskService,
+    method: &str,
+    params: Value,
+    outgoing: &UnboundedSender<String>,
+) -> Result<Value, RpcError> {
+    match method {
+        "status" => Ok(json!(service.status().await)),
+        "height" => Ok(height(service.height().await?)),
+        "raw_height" => Ok(height(service.raw_height().await?)),
+        "move" => {
+            let params: MoveParams = parse(params)?;
+            let source = params.source.unwrap_or(MoveSource::Socket);
+            let (target, reached) = match (params.target, params.height_mm) {
+                (Some(target), None) => {
+                    let unit = service.config().await.display_unit;
+                    let request = MoveRequest::parse(&target, unit).map_err(RpcError::invalid_params)?;
+                    service.move_to(request, params.profile.as_deref(), source).await?
+                }
+                (None, Some(target)) if params.profile.is_some() => {
+                    let request = MoveRequest::Absolute(target);
+                    service.move_to(request, params.profile.as_deref(), source).await?
+                }
+                (None, Some(target)) => (target, service.move_to_height(target, source).await?),
+                _ => return Err(RpcError::invalid_params("expected either target or height_mm")),
+            };
+            Ok(json!({ "target_mm": target, "height_mm": reached }))
+        }
+        "move_for" => {
+            let params: MoveForParams = parse(params)?;
+            let duration = Duration::try_from_secs_f32(params.seconds)
+                .map_err(|_| RpcError::invalid_params(format!("invalid duration {}", params.seconds)))?;
+            let source = params.source.unwrap_or(MoveSource::Socket);
+            Ok(height(service.move_for(params.direction.into(), duration, source).await?))
+        }
+        "hold" => {
+            let params: DirectionParams = parse(params)?;
+            service.hold(params.direction.into()).await?;
+            Ok(Value::Null)
+        }
+        "end_stop" => {
+            let params: DirectionParams = parse(params)?;
+            Ok(height(service.move_to_end_stop(params.direction.into()).await?))
+        }
+        "stop" => Ok(height(service.stop().await?)),
+        "config.get" => {
+            let params: KeyParams = parse(params)?;
+            let config = service.config().await;
+            Ok(config_key(&params.key, &config)?.get(&config)?)
+        }
+        "config.set" => {
+            let params: KeyParams = parse(params)?;
+            let value = params.value.ok_or_else(|| RpcError::invalid_params("missing value"))?;
+            let key = config_key(&params.key, &service.config().await)?;
+            let updated = service.update_config(|config| key.set(config, &value)).await?;
+            Ok(key.get(&updated)?)
+        }
+        "config.unset" => {
+            let params: KeyParams = parse(params)?;
+            let key = config_key(&params.key, &service.config().await)?;
+            let updated = service.update_config(|config| key.unset(config)).await?;
+            Ok(key.get(&updated)?)
+        }
+        "config.list" => {
+            let config = service.config().await;
+            let values = ConfigKey::all(&config)
+                .iter()
+                .map(|key| Ok((key.to_string(), key.get(&config)?)))
+                .collect::<Result<serde_json::Map<_, _>>>()?;
+            Ok(Value::Object(values))
+        }
+        "subscribe" => {
+            subscribe(service, outgoing.clone());
+            Ok(json!({ "subscribed": true }))
+        }
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+    }
+}
+
+/// Forward desk events to the client until it goes away
+fn subscribe(service: &DeskService, outgoing: UnboundedSender<String>) {
+    let (current, mut events) = service.subscribe();
+    tokio::spawn(async move {
+        for event in current {
+            send(&outgoing, &Notification::event(event));
+        }
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => send(&outgoing, &Notification::event(event)),
+                    Err(RecvError::Lagged(missed)) => log::debug!("Subscriber missed {} events", missed),
+                    Err(RecvError::Closed) => return,
+                },
+                _ = outgoing.closed() => return,
+            }
+        }
+    });
+}
+
+fn parse<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    // Methods without required params may be called without any
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(RpcError::invalid_params)
+}
+
+/// A dotted config key, profile settings pinned to the daemon's profile
+fn config_key(key: &str, config: &crate::config::Config) -> Result<ConfigKey, RpcError> {
+    key.parse::<ConfigKey>()
+        .map(|key| key.for_profile(config.active_profile_name()))
+        .map_err(RpcError::invalid_params)
+}
+
+fn height(height: Height) -> Value {
+    json!({ "height_mm": height })
+}
+
+fn send<T: serde::Serialize>(outgoing: &UnboundedSender<String>, message: &T) {
+    match serde_json::to_string(message) {
+        Ok(line) => {
+            let _ = outgoing.send(line);
+        }
+        Err(e) => log::error!("Failed to encode a message: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::daemon::protocol::INVALID_PARAMS;
+
+    async fn call(service: &DeskService, line: &str) -> Response {
+        let (outgoing, _) = mpsc::unbounded_channel();
+        respond(service, line, &outgoing).await.expect("a response")
+    }
+
+    #[tokio::test]
+    async fn test_rejects_malformed_requests() {
+        let service = DeskService::new(Config::default());
+
+        let response = call(&service, "{not json").await;
+        assert_eq!(response.error.unwrap().code, PARSE_ERROR);
+
+        let response = call(&service, r#"{"jsonrpc":"1.0","id":1,"method":"status"}"#).await;
+        assert_eq!(response.id, json!(1));
+        assert_eq!(response.error.unwrap().code, INVALID_REQUEST);
+
+        let response = call(&service, r#"{"jsonrpc":"2.0","id":2,"method":"jump"}"#).await;
+        assert_eq!(response.error.unwrap().code, METHOD_NOT_FOUND);
+
+        let response =
+            call(&service, r#"{"jsonrpc":"2.0","id":3,"method":"move","params":{"target":"grande","height_mm":900}}"#)
+                .await;
+        assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+
+        let response =
+            call(&service, r#"{"jsonrpc":"2.0","id":4,"method":"move_for","params":{"direction":"sideways"}}"#).await;
+        assert_eq!(response.error.unwrap().code, INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_answers_requests_and_skips_notifications() {
+        let service = DeskService::new(Config::default());
+
+        let response = call(&service, r#"{"jsonrpc":"2.0","id":"a","method":"status"}"#).await;
+        let status = response.result.unwrap();
+        assert_eq!(status["connected"], false);
+        assert_eq!(status["profile"], "default");
+
+        // Without a desk, moves fail with the CLI's exit code attached
+        let response = call(&service, r#"{"jsonrpc":"2.0","id":5,"method":"move","params":{"target":"grande"}}"#).await;
+        let error = response.error.unwrap().into_desk_error();
+        assert_eq!(error.exit_code(), crate::desk::error::exit_code::NOT_CONFIGURED);
+
+        let (outgoing, _) = mpsc::unbounded_channel();
+        assert!(respond(&service, r#"{"jsonrpc":"2.0","method":"status"}"#, &outgoing)
+            .await
+            .is_none());
+    }
+}
@@ -0,0 +1,551 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, watch, Mutex};
+use tokio::task::AbortHandle;
+use tokio::time::timeout;
+
+use crate::backoff::{self, Backoff};
+use crate::config::{Config, DrinkSize};
+use crate::desk::watch::{self as desk_watch, WatchEvent};
+use crate::desk::{Calibration, DeskController, DeskError, DeskResult, Height, MovementCommand};
+use crate::metrics::{self, MoveSource};
+use crate::movement::MoveRequest;
+
+/// How long a request waits for the desk while the daemon (re)connects
+const CONNECT_WAIT: Duration = Duration::from_secs(30);
+
+/// A connection attempt taking longer than this means the Bluetooth stack is stuck;
+/// attempts give up on their own well before
+const CONNECT_STUCK: Duration = Duration::from_secs(180);
+
+/// How long a connected desk may take to confirm its link before it counts as stuck
+const LINK_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Events kept for a slow subscriber before it starts missing some
+const EVENT_BUFFER: usize = 64;
+
+/// Settings that need a fresh connection when they change
+#[derive(Debug, Clone, PartialEq)]
+struct Link {
+    address: Option<String>,
+    calibration: Calibration,
+}
+
+impl Link {
+    fn of(config: &Config) -> Self {
+        Self {
+            address: config.desk_address.clone(),
+            calibration: config.calibration(),
+        }
+    }
+}
+
+/// The daemon's view of the desk, as returned by the `status` method
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub desk_address: Option<String>,
+    pub profile: String,
+    pub connected: bool,
+    pub height_mm: Option<Height>,
+    pub moving: bool,
+    /// Why the desk isn't connected, if it lost the link
+    pub error: Option<String>,
+}
+
+/// Last connection state and reading, replayed to new subscribers
+#[derive(Debug, Default)]
+struct Snapshot {
+    connection: Option<WatchEvent>,
+    reading: Option<WatchEvent>,
+    /// When the connection attempt in progress started
+    connecting_since: Option<Instant>,
+}
+
+impl Snapshot {
+    fn events(&self) -> Vec<WatchEvent> {
+        self.connection.iter().chain(&self.reading).cloned().collect()
+    }
+}
+
+/// Owns the connection to the desk and carries out every client's requests
+///
+/// Only one process can hold the desk's Bluetooth link, so the tray, the CLI
+/// and scripts all go through this service, in-process or over the socket.
+pub struct DeskService {
+    config: Mutex<Config>,
+    link: watch::Sender<Link>,
+    /// The connected desk, `None` while (re)connecting
+    desk: watch::Sender<Option<Arc<DeskController>>>,
+    /// Marked changed whenever a new config is taken
+    reloads: watch::Sender<()>,
+    snapshot: StdMutex<Snapshot>,
+    events: broadcast::Sender<WatchEvent>,
+    /// The movement in progress, so a new one (or a stop) can take over
+    motion: StdMutex<Option<AbortHandle>>,
+}
+
+impl DeskService {
+    pub fn new(config: Config) -> Arc<Self> {
+        Arc::new(Self {
+            link: watch::Sender::new(Link::of(&config)),
+            config: Mutex::new(config),
+            desk: watch::Sender::new(None),
+            reloads: watch::Sender::new(()),
+            snapshot: StdMutex::new(Snapshot::default()),
+            events: broadcast::channel(EVENT_BUFFER).0,
+            motion: StdMutex::new(None),
+        })
+    }
+
+    /// Keep the configured desk connected, reconnecting with a growing delay
+    ///
+    /// Switches desks (or recalibrates) whenever the config changes them.
+    pub async fn run(self: Arc<Self>) {
+        let mut link_changes = self.link.subscribe();
+        let mut retry = Backoff::new(backoff::DESK);
+        let mut connected_before = false;
+
+        // Readings go through a channel so the desk watcher's `follow` can be reused
+        let (readings, mut received) = mpsc::unbounded_channel();
+        let service = Arc::clone(&self);
+        tokio::spawn(async move {
+            while let Some(event) = received.recv().await {
+                service.publish(event);
+            }
+        });
+
+        loop {
+            let link = link_changes.borrow_and_update().clone();
+            let Some(address) = link.address else {
+                if link_changes.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            };
+
+            self.publish(WatchEvent::Connecting { address: address.clone() });
+            let connected = tokio::select! {
+                result = DeskController::connect(Some(address.clone()), link.calibration) => result,
+                _ = link_changes.changed() => continue,
+            };
+
+            let error = match connected {
+                Ok(desk) => {
+                    retry.reset();
+                    if connected_before {
+                        metrics::reconnected();
+                    }
+                    connected_before = true;
+                    let desk = Arc::new(desk);
+                    self.desk.send_replace(Some(Arc::clone(&desk)));
+                    self.publish(WatchEvent::Connected { address: address.clone() });
+                    log::info!("Connected to desk at {}", address);
+
+                    let followed = tokio::select! {
+                        result = desk_watch::follow(&desk, &readings) => Some(result),
+                        _ = link_changes.changed() => None,
+                    };
+                    self.release(&desk).await;
+
+                    match followed {
+                        Some(Ok(())) => anyhow!("Desk disconnected"),
+                        Some(Err(e)) => e,
+                        None => {
+                            log::info!("Desk settings changed, reconnecting");
+                            self.publish(WatchEvent::Disconnected {
+                                error: "Desk settings changed".to_string(),
+                                retry_in_secs: 0,
+                            });
+                            continue;
+                        }
+                    }
+                }
+                Err(e) => e.into(),
+            };
+
+            log::warn!("Lost desk connection: {}", error);
+            self.publish(WatchEvent::Disconnected {
+                error: error.to_string(),
+                retry_in_secs: retry.delay().as_secs(),
+            });
+
+            tokio::select! {
+                _ = retry.wait() => {}
+                _ = link_changes.changed() => {}
+            }
+        }
+    }
+
+    /// Stop whatever is moving and drop the link, e.g. before the daemon exits
+    pub async fn shutdown(&self) {
+        let desk = self.desk.borrow().clone();
+        if let Some(desk) = desk {
+            self.release(&desk).await;
+        }
+    }
+
+    /// Forget `desk`, stopping any movement on it, and disconnect
+    async fn release(&self, desk: &DeskController) {
+        self.desk.send_replace(None);
+        if self.interrupt() {
+            if let Err(e) = desk.stop().await {
+                log::debug!("Failed to stop the desk: {}", e);
+            }
+        }
+        if let Err(e) = desk.disconnect().await {
+            log::debug!("Failed to disconnect cleanly: {}", e);
+        }
+    }
+
+    /// Record `event` for `status` and new subscribers, and send it to current ones
+    fn publish(&self, event: WatchEvent) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        match &event {
+            WatchEvent::Connecting { .. } | WatchEvent::Disconnected { .. } => {
+                snapshot.connecting_since = match event {
+                    WatchEvent::Connecting { .. } => Some(Instant::now()),
+                    _ => None,
+                };
+                snapshot.connection = Some(event.clone());
+                snapshot.reading = None;
+                metrics::set_connected(false);
+            }
+            WatchEvent::Connected { .. } => {
+                snapshot.connecting_since = None;
+                snapshot.connection = Some(event.clone());
+                metrics::set_connected(true);
+            }
+            WatchEvent::Height { height_mm, .. } => {
+                metrics::set_height(*height_mm);
+                snapshot.reading = Some(event.clone());
+            }
+            _ => {}
+        }
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    /// Events describing the current state, followed by every later change
+    pub fn subscribe(&self) -> (Vec<WatchEvent>, broadcast::Receiver<WatchEvent>) {
+        let snapshot = self.snapshot.lock().unwrap();
+        (snapshot.events(), self.events.subscribe())
+    }
+
+    /// Take a config re-read from disk, keeping this session's profile
+    pub async fn reload(&self, mut config: Config) {
+        let link = Link::of(&config);
+        {
+            let mut current = self.config.lock().await;
+            config.inherit_session_profile(&current);
+            *current = config;
+        }
+        self.link.send_if_modified(|current| {
+            let changed = *current != link;
+            *current = link;
+            changed
+        });
+        self.reloads.send_replace(());
+    }
+
+    /// Notified after every reload, for components that read the config once
+    pub fn reloads(&self) -> watch::Receiver<()> {
+        self.reloads.subscribe()
+    }
+
+    /// Change the config file and use the result right away
+    pub async fn update_config<F>(&self, change: F) -> Result<Config>
+    where
+        F: FnOnce(&mut Config) -> Result<()>,
+    {
+        let updated = Config::try_update(|config| {
+            change(config)?;
+            config.validate()
+        })?;
+        self.reload(updated).await;
+        Ok(self.config().await)
+    }
+
+    /// The config in use, with this session's profile selected
+    pub async fn config(&self) -> Config {
+        self.config.lock().await.clone()
+    }
+
+    pub async fn status(&self) -> Status {
+        let config = self.config().await;
+        let snapshot = self.snapshot.lock().unwrap();
+
+        let mut status = Status {
+            desk_address: config.desk_address.clone(),
+            profile: config.active_profile_name().to_string(),
+            ..Status::default()
+        };
+        match &snapshot.connection {
+            Some(WatchEvent::Connected { .. }) => status.connected = true,
+            Some(WatchEvent::Disconnected { error, .. }) => status.error = Some(error.clone()),
+            _ => {}
+        }
+        if let Some(WatchEvent::Height { height_mm, moving, .. }) = &snapshot.reading {
+            status.height_mm = Some(*height_mm);
+            status.moving = *moving;
+        }
+        status
+    }
+
+    /// Whether the desk task is still making progress, for the systemd watchdog
+    ///
+    /// A connected desk has to confirm its link in time, and a connection
+    /// attempt must not hang. Waiting to retry or for a desk to be configured is fine.
+    pub async fn is_responsive(&self) -> bool {
+        let connecting_since = self.snapshot.lock().unwrap().connecting_since;
+        if connecting_since.is_some_and(|since| since.elapsed() > CONNECT_STUCK) {
+            return false;
+        }
+
+        let desk = self.desk.borrow().clone();
+        match desk {
+            Some(desk) => confirms_link(desk.is_connected()).await,
+            None => true,
+        }
+    }
+
+    /// The connected desk, waiting for a connection that is being set up
+    async fn desk(&self) -> DeskResult<Arc<DeskController>> {
+        if self.link.borrow().address.is_none() {
+            return Err(DeskError::NotConfigured);
+        }
+
+        let mut connected = self.desk.subscribe();
+        let desk = match timeout(CONNECT_WAIT, connected.wait_for(Option::is_some)).await {
+            Ok(Ok(desk)) => desk.clone(),
+            _ => None,
+        };
+        desk.ok_or(DeskError::Timeout {
+            operation: "waiting for the desk to connect",
+            after: CONNECT_WAIT,
+        })
+    }
+
+    pub async fn height(&self) -> DeskResult<Height> {
+        self.desk().await?.get_height().await
+    }
+
+    /// Height as the desk reports it, for the calibration wizard
+    pub async fn raw_height(&self) -> DeskResult<Height> {
+        self.desk().await?.get_raw_height().await
+    }
+
+    /// Resolve `request` with the daemon's config (or `profile`) and move there
+    ///
+    /// Returns the target and the height the desk reached.
+    pub async fn move_to(
+        &self,
+        request: MoveRequest,
+        profile: Option<&str>,
+        source: MoveSource,
+    ) -> Result<(Height, Height)> {
+        let mut config = self.config().await;
+        if profile.is_some() {
+            config.select_session_profile(profile)?;
+        }
+
+        let current = match request.is_relative() {
+            true => self.height().await,
+            false => Ok(Height::default()),
+        };
+        let target = current
+            .and_then(|current| request.resolve(&config, current))
+            .inspect_err(|e| metrics::record_move(source, Some(e), None))?;
+        let preset = match request {
+            MoveRequest::Preset(preset) => Some(preset),
+            _ => None,
+        };
+        let reached = self.move_checked(target, preset, source).await?;
+        Ok((target, reached))
+    }
+
+    /// Move to exactly `target`, within the active profile's limits, and
+    /// return the height reached
+    pub async fn move_to_height(&self, target: Height, source: MoveSource) -> DeskResult<Height> {
+        self.config()
+            .await
+            .move_limits()
+            .check(target)
+            .inspect_err(|e| metrics::record_move(source, Some(e), None))?;
+        self.move_checked(target, None, source).await
+    }
+
+    /// Move to `target`, which the caller checked against the limits, as
+    /// asked for by `preset` if it is one
+    async fn move_checked(&self, target: Height, preset: Option<DrinkSize>, source: MoveSource) -> DeskResult<Height> {
+        let desk = self
+            .desk()
+            .await
+            .inspect_err(|e| metrics::record_move(source, Some(e), None))?;
+        let started = Instant::now();
+        self.publish(WatchEvent::MoveStarted { target_mm: target, preset });
+
+        let result = self
+            .exclusive(async move {
+                desk.move_to_height(target).await?;
+                desk.get_height().await
+            })
+            .await;
+
+        self.publish(match &result {
+            Ok(height) => WatchEvent::MoveFinished {
+                target_mm: target,
+                height_mm: *height,
+            },
+            Err(e) => WatchEvent::MoveFailed {
+                target_mm: target,
+                error: e.to_string(),
+            },
+        });
+        metrics::record_move(source, result.as_ref().err(), Some(started.elapsed()));
+        result
+    }
+
+    /// Keep moving in `direction` for `duration`, then stop
+    pub async fn move_for(
+        &self,
+        direction: MovementCommand,
+        duration: Duration,
+        source: MoveSource,
+    ) -> DeskResult<Height> {
+        let desk = self
+            .desk()
+            .await
+            .inspect_err(|e| metrics::record_move(source, Some(e), None))?;
+        let started = Instant::now();
+        let result = self
+            .exclusive(async move {
+                desk.move_for(direction, duration).await?;
+                desk.get_height().await
+            })
+            .await;
+        metrics::record_move(source, result.as_ref().err(), Some(started.elapsed()));
+        result
+    }
+
+    /// Send a single up or down command; the desk keeps going while these repeat
+    pub async fn hold(&self, direction: MovementCommand) -> DeskResult<()> {
+        let desk = self.desk().await?;
+        self.exclusive(async move { desk.send_command(direction).await }).await
+    }
+
+    /// Drive the desk until it halts at its end stop, for the setup wizards
+    pub async fn move_to_end_stop(&self, direction: MovementCommand) -> DeskResult<Height> {
+        let desk = self.desk().await?;
+        self.exclusive(async move { desk.move_to_end_stop(direction).await }).await
+    }
+
+    /// Interrupt any movement and stop the desk
+    pub async fn stop(&self) -> DeskResult<Height> {
+        let desk = self.desk().await?;
+        self.interrupt();
+        desk.stop().await?;
+        desk.get_height().await
+    }
+
+    /// Run a movement in its own task, replacing whichever one was running
+    ///
+    /// The replaced movement's caller gets [`DeskError::Interrupted`].
+    async fn exclusive<T, F>(&self, movement: F) -> DeskResult<T>
+    where
+        T: Send + 'static,
+        F: Future<Output = DeskResult<T>> + Send + 'static,
+    {
+        let task = tokio::spawn(movement);
+        if let Some(previous) = self.motion.lock().unwrap().replace(task.abort_handle()) {
+            previous.abort();
+        }
+
+        match task.await {
+            Ok(result) => result,
+            Err(e) if e.is_cancelled() => Err(DeskError::Interrupted),
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /// Abort the movement in progress; returns whether there was one
+    fn interrupt(&self) -> bool {
+        match self.motion.lock().unwrap().take() {
+            Some(motion) => {
+                let running = !motion.is_finished();
+                motion.abort();
+                running
+            }
+            None => false,
+        }
+    }
+}
+
+/// Whether a link check answered in time and found the link up
+///
+/// A desk that answers "not connected" has dropped its link, which counts
+/// as unresponsive as much as one that doesn't answer.
+async fn confirms_link(check: impl std::future::Future<Output = bool>) -> bool {
+    matches!(timeout(LINK_CHECK_TIMEOUT, check).await, Ok(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_status_follows_events() {
+        let service = DeskService::new(Config::default());
+        let address = "AA:BB:CC:DD:EE:FF".to_string();
+
+        service.publish(WatchEvent::Connected { address: address.clone() });
+        service.publish(WatchEvent::Height {
+            height_mm: Height::from_units(10500),
+            speed: 0,
+            moving: false,
+        });
+        let status = service.status().await;
+        assert!(status.connected);
+        assert_eq!(status.height_mm, Some(Height::from_units(10500)));
+
+        // New subscribers start from the current state
+        let (current, _) = service.subscribe();
+        assert_eq!(current.len(), 2);
+
+        service.publish(WatchEvent::Disconnected {
+            error: "Desk disconnected".to_string(),
+            retry_in_secs: 2,
+        });
+        let status = service.status().await;
+        assert!(!status.connected);
+        assert_eq!(status.height_mm, None);
+        assert_eq!(status.error.as_deref(), Some("Desk disconnected"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_need_a_configured_desk() {
+        let service = DeskService::new(Config::default());
+        assert!(matches!(service.height().await, Err(DeskError::NotConfigured)));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_links_are_unresponsive() {
+        assert!(confirms_link(std::future::ready(true)).await);
+        assert!(!confirms_link(std::future::ready(false)).await);
+    }
+
+    #[tokio::test]
+    async fn test_exact_heights_respect_the_limits() {
+        let mut config = Config::default();
+        config.profile_mut().limits.max = Some(Height::from_units(11000));
+        let service = DeskService::new(config);
+
+        let result = service.move_to_height(Height::from_units(12000), MoveSource::Socket).await;
+        assert!(matches!(result, Err(DeskError::AboveMaximum { .. })));
+        // Within the limits, the move gets as far as needing a desk
+        let result = service.move_to_height(Height::from_units(10000), MoveSource::Socket).await;
+        assert!(matches!(result, Err(DeskError::NotConfigured)));
+    }
+}
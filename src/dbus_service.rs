@@ -0,0 +1,397 @@
+use anyhow::{anyhow, Context as _, Result};
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::{PropertiesPropertiesChanged, RequestNameReply};
+use dbus::blocking::SyncConnection;
+use dbus::channel::{default_reply, MatchingReceiver, Sender};
+use dbus::message::{MatchRule, SignalArgs};
+use dbus::strings::Path;
+use dbus::{Message, MethodErr};
+use dbus_crossroads::{Context, Crossroads, IfaceBuilder};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use crate::config::{Config, DrinkSize};
+use crate::desk::error::exit_code;
+use crate::desk::watch::WatchEvent;
+use crate::desk::{DeskError, Height};
+
+pub const BUS_NAME: &str = "io.github.DeskControl";
+pub const OBJECT_PATH: &str = "/io/github/DeskControl";
+pub const INTERFACE: &str = "io.github.DeskControl";
+
+const PEER_INTERFACE: &str = "org.freedesktop.DBus.Peer";
+
+/// What the exported object shows as its properties
+#[derive(Debug, Clone, PartialEq)]
+pub struct Properties {
+    /// Last reported height, 0 on the bus while unknown
+    pub height: Option<Height>,
+    /// "connecting", "connected" or "disconnected"
+    pub connection_state: String,
+    /// Preset heights of the active profile, by preset name
+    pub presets: BTreeMap<String, Height>,
+}
+
+impl Properties {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            height: None,
+            connection_state: "disconnected".to_string(),
+            presets: presets_of(config),
+        }
+    }
+
+    /// Follow a desk event; returns whether anything changed
+    pub fn apply(&mut self, event: &WatchEvent) -> bool {
+        let before = self.clone();
+        match event {
+            WatchEvent::Connecting { .. } => self.connection_state = "connecting".to_string(),
+            WatchEvent::Connected { .. } => self.connection_state = "connected".to_string(),
+            WatchEvent::Disconnected { .. } => self.connection_state = "disconnected".to_string(),
+            WatchEvent::Height { height_mm, .. } => self.height = Some(*height_mm),
+            _ => {}
+        }
+        *self != before
+    }
+
+    /// Follow a config change; returns whether the presets changed
+    pub fn set_config(&mut self, config: &Config) -> bool {
+        let presets = presets_of(config);
+        let changed = presets != self.presets;
+        self.presets = presets;
+        changed
+    }
+
+    /// The property called `name` as a D-Bus value
+    fn get(&self, name: &str) -> Option<Variant<Box<dyn RefArg>>> {
+        let value: Box<dyn RefArg> = match name {
+            "Height" => Box::new(self.height.map_or(0.0, Height::as_mm)),
+            "ConnectionState" => Box::new(self.connection_state.clone()),
+            "Presets" => Box::new(
+                self.presets
+                    .iter()
+                    .map(|(name, height)| (name.clone(), height.as_mm()))
+                    .collect::<HashMap<_, _>>(),
+            ),
+            _ => return None,
+        };
+        Some(Variant(value))
+    }
+
+    /// Every property, or only those that differ from `previous`
+    fn changed_since(&self, previous: Option<&Self>) -> PropMap {
+        let mut changed = PropMap::new();
+        let mut add = |name: &str, differs: bool| {
+            if differs {
+                if let Some(value) = self.get(name) {
+                    changed.insert(name.to_string(), value);
+                }
+            }
+        };
+        add("Height", previous.is_none_or(|p| p.height != self.height));
+        add("ConnectionState", previous.is_none_or(|p| p.connection_state != self.connection_state));
+        add("Presets", previous.is_none_or(|p| p.presets != self.presets));
+        changed
+    }
+}
+
+fn presets_of(config: &Config) -> BTreeMap<String, Height> {
+    DrinkSize::all()
+        .into_iter()
+        .map(|preset| (preset.name().to_string(), config.get_preset(preset)))
+        .collect()
+}
+
+/// A method call that needs the desk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Call {
+    MoveToPreset(DrinkSize),
+    MoveToHeight(Height),
+    Stop,
+}
+
+/// Answers a method call once the desk is done with it
+pub struct Reply {
+    connection: Arc<SyncConnection>,
+    context: Context,
+}
+
+impl Reply {
+    /// Answer with the height moved to, or nothing for `Stop`
+    pub fn send(mut self, height: Option<Height>) {
+        if let Some(height) = height {
+            self.context.reply(Ok((height.as_mm(),)));
+        } else {
+            self.context.reply(Ok(()));
+        }
+        self.flush();
+    }
+
+    pub fn fail(mut self, error: &DeskError) {
+        let text = error.to_string().replace('\0', "");
+        self.context.reply::<()>(Err(MethodErr::from((error_name(error), text))));
+        self.flush();
+    }
+
+    /// Send the reply, unless the caller asked not to get one
+    fn flush(mut self) {
+        let _ = self.context.flush_messages(&*self.connection);
+    }
+}
+
+/// The `io.github.DeskControl` object on the session bus
+///
+/// Lives on the UI thread: `process` is called from the GTK main loop, which
+/// answers property reads, introspection and pings right away and hands moves
+/// to the caller.
+pub struct DbusService {
+    connection: Arc<SyncConnection>,
+    messages: mpsc::Receiver<Message>,
+    crossroads: Crossroads,
+    calls: mpsc::Receiver<(Call, Context)>,
+    properties: Properties,
+}
+
+impl DbusService {
+    /// Claim the bus name and start receiving method calls
+    pub fn start(properties: Properties) -> Result<Self> {
+        let connection = SyncConnection::new_session().context("Cannot reach the session bus")?;
+        let claimed = connection
+            .request_name(BUS_NAME, false, true, true)
+            .with_context(|| format!("Failed to claim {} on the session bus", BUS_NAME))?;
+        if claimed != RequestNameReply::PrimaryOwner {
+            return Err(anyhow!("{} is already owned by another process", BUS_NAME));
+        }
+
+        let (queue, messages) = mpsc::channel();
+        connection.start_receive(
+            MatchRule::new_method_call(),
+            Box::new(move |message, _| queue.send(message).is_ok()),
+        );
+
+        let (defer, calls) = mpsc::channel();
+        log::info!("Exported {} on the session bus", BUS_NAME);
+        Ok(Self {
+            connection: Arc::new(connection),
+            messages,
+            crossroads: crossroads(properties.clone(), defer),
+            calls,
+            properties,
+        })
+    }
+
+    /// Handle the method calls that arrived since the last time
+    ///
+    /// `run` gets the calls that need the desk and must answer them through the
+    /// `Reply`.
+    pub fn process(&mut self, mut run: impl FnMut(Call, Reply)) {
+        if let Err(e) = self.connection.process(Duration::ZERO) {
+            log::warn!("D-Bus connection failed: {}", e);
+            return;
+        }
+
+        while let Ok(message) = self.messages.try_recv() {
+            // Ping and GetMachineId work on any path, so they skip the object tree
+            if message.interface().as_deref() == Some(PEER_INTERFACE) {
+                if let Some(reply) = default_reply(&message).filter(|_| !message.get_no_reply()) {
+                    let _ = self.connection.send(reply);
+                }
+                continue;
+            }
+            let _ = self.crossroads.handle_message(message, &*self.connection);
+        }
+
+        while let Ok((call, context)) = self.calls.try_recv() {
+            let connection = Arc::clone(&self.connection);
+            run(call, Reply { connection, context });
+        }
+    }
+
+    /// Show new property values, signalling the ones that changed
+    pub fn update(&mut self, properties: Properties) {
+        let changed = properties.changed_since(Some(&self.properties));
+        if changed.is_empty() {
+            return;
+        }
+        if let Some(served) = self.crossroads.data_mut::<Properties>(&Path::from(OBJECT_PATH)) {
+            served.clone_from(&properties);
+        }
+        self.properties = properties;
+
+        let signal = PropertiesPropertiesChanged {
+            interface_name: INTERFACE.to_string(),
+            changed_properties: changed,
+            invalidated_properties: Vec::new(),
+        };
+        let _ = self
+            .connection
+            .send(signal.to_emit_message(&Path::from(OBJECT_PATH)));
+    }
+
+    pub fn properties(&self) -> &Properties {
+        &self.properties
+    }
+}
+
+/// The object tree: the desk object, and the nodes leading to it for introspection
+///
+/// Moves are not answered here but sent to `defer` with the call's context.
+fn crossroads(properties: Properties, defer: mpsc::Sender<(Call, Context)>) -> Crossroads {
+    let mut crossroads = Crossroads::new();
+    let interface = crossroads.register(INTERFACE, |b: &mut IfaceBuilder<Properties>| {
+        let defer_preset = defer.clone();
+        b.method_with_cr_custom::<(String,), (f64,), _, _>(
+            "MoveToPreset",
+            ("preset",),
+            ("height_mm",),
+            move |mut context, _, (name,)| match DrinkSize::from_name(&name) {
+                Some(preset) => defer_call(&defer_preset, Call::MoveToPreset(preset), context),
+                None => {
+                    context.reply::<()>(Err(invalid_args(format!("Unknown preset '{}'", name))));
+                    Some(context)
+                }
+            },
+        );
+        let defer_height = defer.clone();
+        b.method_with_cr_custom::<(f64,), (f64,), _, _>(
+            "MoveToHeight",
+            ("height_mm",),
+            ("height_mm",),
+            move |mut context, _, (mm,)| match Height::from_mm_f64(mm) {
+                Some(height) => defer_call(&defer_height, Call::MoveToHeight(height), context),
+                None => {
+                    context.reply::<()>(Err(invalid_args(format!("Invalid height {}mm", mm))));
+                    Some(context)
+                }
+            },
+        );
+        b.method_with_cr_custom::<(), (), _, _>("Stop", (), (), move |context, _, ()| {
+            defer_call(&defer, Call::Stop, context)
+        });
+
+        b.property::<f64, _>("Height")
+            .get(|_, properties| Ok(properties.height.map_or(0.0, Height::as_mm)));
+        b.property::<String, _>("ConnectionState")
+            .get(|_, properties| Ok(properties.connection_state.clone()));
+        b.property::<HashMap<String, f64>, _>("Presets").get(|_, properties| {
+            Ok(properties
+                .presets
+                .iter()
+                .map(|(name, height)| (name.clone(), height.as_mm()))
+                .collect())
+        });
+    });
+
+    let mut node = String::new();
+    for part in OBJECT_PATH.trim_start_matches('/').split('/') {
+        node = format!("{}/{}", node, part);
+        if node != OBJECT_PATH {
+            crossroads.insert(node.clone(), &[], ());
+        }
+    }
+    crossroads.insert(OBJECT_PATH, &[interface], properties);
+    crossroads
+}
+
+/// Hand a move to `process`, or fail it if the service is gone
+fn defer_call(defer: &mpsc::Sender<(Call, Context)>, call: Call, context: Context) -> Option<Context> {
+    match defer.send((call, context)) {
+        Ok(()) => None,
+        Err(mpsc::SendError((_, mut context))) => {
+            context.reply::<()>(Err(MethodErr::failed("Desk Control is shutting down")));
+            Some(context)
+        }
+    }
+}
+
+/// D-Bus error name for a failed desk call, following the CLI's exit codes
+fn error_name(error: &DeskError) -> &'static str {
+    match error.exit_code() {
+        exit_code::NOT_CONFIGURED => "io.github.DeskControl.Error.NotConfigured",
+        exit_code::NOT_FOUND => "io.github.DeskControl.Error.NotFound",
+        exit_code::BLUETOOTH => "io.github.DeskControl.Error.Bluetooth",
+        exit_code::CONNECTION => "io.github.DeskControl.Error.Connection",
+        exit_code::TIMEOUT => "io.github.DeskControl.Error.Timeout",
+        exit_code::OBSTRUCTED => "io.github.DeskControl.Error.Obstructed",
+        exit_code::OUT_OF_RANGE => "io.github.DeskControl.Error.OutOfRange",
+        _ => "io.github.DeskControl.Error.Failed",
+    }
+}
+
+fn invalid_args(text: String) -> MethodErr {
+    MethodErr::from(("org.freedesktop.DBus.Error.InvalidArgs", text))
+}
+
+impl std::fmt::Display for Call {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MoveToPreset(preset) => write!(f, "MoveToPreset({})", preset.name()),
+            Self::MoveToHeight(height) => write!(f, "MoveToHeight({}mm)", height.as_mm()),
+            Self::Stop => write!(f, "Stop"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_properties_follow_events_and_config() {
+        let mut config = Config::default();
+        let mut properties = Properties::new(&config);
+        assert_eq!(properties.connection_state, "disconnected");
+        assert_eq!(properties.presets.len(), 4);
+
+        assert!(properties.apply(&WatchEvent::Connected { address: "AA".to_string() }));
+        let height = Height::from_mm(900).unwrap();
+        let event = WatchEvent::Height { height_mm: height, speed: 0, moving: false };
+        assert!(properties.apply(&event));
+        assert!(!properties.apply(&event));
+
+        let before = properties.clone();
+        config.set_preset(DrinkSize::Grande, Height::from_mm(1000).unwrap());
+        assert!(properties.set_config(&config));
+        let changed = properties.changed_since(Some(&before));
+        assert_eq!(changed.keys().collect::<Vec<_>>(), ["Presets"]);
+        assert_eq!(properties.changed_since(None).len(), 3);
+    }
+
+    fn call(path: &str, interface: &str, member: &str) -> Message {
+        let mut message = Message::new_method_call(BUS_NAME, path, interface, member).unwrap();
+        message.set_serial(1);
+        message
+    }
+
+    #[test]
+    fn test_serves_the_object_and_defers_moves() {
+        let (defer, calls) = mpsc::channel();
+        let mut crossroads = crossroads(Properties::new(&Config::default()), defer);
+        let sent = RefCell::new(Vec::new());
+        let mut answer = |message: Message| {
+            crossroads.handle_message(message, &sent).unwrap();
+            sent.borrow_mut().pop()
+        };
+
+        let introspect = |path| call(path, "org.freedesktop.DBus.Introspectable", "Introspect");
+        let xml = |reply: Option<Message>| reply.unwrap().read1::<String>().unwrap();
+        assert!(xml(answer(introspect("/"))).contains("<node name=\"io\"/>"));
+        assert!(xml(answer(introspect("/io/github"))).contains("<node name=\"DeskControl\"/>"));
+        assert!(xml(answer(introspect(OBJECT_PATH))).contains("MoveToPreset"));
+
+        let get = call(OBJECT_PATH, "org.freedesktop.DBus.Properties", "Get").append2(INTERFACE, "ConnectionState");
+        let state = answer(get).unwrap().read1::<Variant<String>>().unwrap();
+        assert_eq!(state.0, "disconnected");
+
+        assert!(answer(call(OBJECT_PATH, INTERFACE, "MoveToPreset").append1("venti")).is_none());
+        assert_eq!(calls.try_recv().unwrap().0, Call::MoveToPreset(DrinkSize::Venti));
+        let unknown = answer(call(OBJECT_PATH, INTERFACE, "MoveToPreset").append1("huge")).unwrap();
+        assert_eq!(unknown.msg_type(), dbus::MessageType::Error);
+        assert!(calls.try_recv().is_err());
+
+        let ping = call(OBJECT_PATH, PEER_INTERFACE, "Ping");
+        assert_eq!(default_reply(&ping).unwrap().msg_type(), dbus::MessageType::MethodReturn);
+    }
+}
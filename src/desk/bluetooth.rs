@@ -1,32 +1,93 @@
-use anyhow::{anyhow, Context, Result};
 use btleplug::api::{
-    Central, Characteristic, Manager as _, Peripheral as _, ScanFilter, WriteType,
+    Central, Characteristic, Manager as _, Peripheral as _, PeripheralProperties, ScanFilter,
+    WriteType,
 };
-use btleplug::platform::{Adapter, Manager, Peripheral};
+use btleplug::platform::{Manager, Peripheral};
+use futures::{Stream, StreamExt};
 use std::time::Duration;
 use tokio::time::sleep;
 
+use super::calibration::Calibration;
+use super::error::{DeskError, DeskResult};
+use super::height::Height;
+use super::scan::ScanReport;
+use crate::metrics::{self, BleOperation};
 use super::protocol::{
-    parse_height, CONTROL_CHARACTERISTIC_UUID, CONTROL_SERVICE_UUID, HEIGHT_CHARACTERISTIC_UUID,
-    MovementCommand,
+    parse_height, parse_reading, DeskReading, MovementCommand, CONTROL_CHARACTERISTIC_UUID,
+    HEIGHT_CHARACTERISTIC_UUID,
 };
 
+/// How close to the target counts as arrived (5mm)
+const TOLERANCE: Height = Height::from_units(50);
+
+/// Minimum change after a MoveToHeight command that shows the desk responded (5mm)
+const MOVEMENT_THRESHOLD: Height = Height::from_units(50);
+
+/// Change between polls below which a manual move is considered stalled (2mm)
+const STALL_THRESHOLD: Height = Height::from_units(20);
+
 pub struct DeskController {
     peripheral: Peripheral,
     control_char: Option<Characteristic>,
     height_char: Option<Characteristic>,
+    /// Converts between reported and real heights; identity until calibrated
+    calibration: Calibration,
 }
 
 impl DeskController {
-    /// Scan for available Linak desks
-    pub async fn scan_for_desks(timeout_secs: u64) -> Result<Vec<Peripheral>> {
+    /// Scan for BLE devices and report what each one advertises
+    ///
+    /// Desks come first, nearest first. Unless `include_all` is set, devices
+    /// that don't look like a desk are left out.
+    pub async fn scan(
+        timeout_secs: u64,
+        configured_address: Option<&str>,
+        include_all: bool,
+    ) -> DeskResult<Vec<ScanReport>> {
+        let mut reports: Vec<ScanReport> = Self::discover(timeout_secs)
+            .await?
+            .iter()
+            .map(|(_, props)| ScanReport::from_properties(props, configured_address))
+            .filter(|report| include_all || report.is_desk())
+            .collect();
+
+        reports.sort_by_key(ScanReport::relevance);
+        Ok(reports)
+    }
+
+    /// Scan for available Linak desks, nearest first
+    pub async fn scan_for_desks(timeout_secs: u64) -> DeskResult<Vec<Peripheral>> {
+        let mut desks: Vec<(Peripheral, ScanReport)> = Self::discover(timeout_secs)
+            .await?
+            .into_iter()
+            .map(|(peripheral, props)| {
+                let report = ScanReport::from_properties(&props, None);
+                (peripheral, report)
+            })
+            .filter(|(_, report)| report.is_desk())
+            .collect();
+
+        for (_, report) in &desks {
+            log::info!(
+                "Found potential Linak desk: {} ({})",
+                report.name.as_deref().unwrap_or("unnamed"),
+                report.address
+            );
+        }
+
+        desks.sort_by_key(|(_, report)| report.relevance());
+        Ok(desks.into_iter().map(|(peripheral, _)| peripheral).collect())
+    }
+
+    /// Run a BLE scan and collect every peripheral with its properties
+    async fn discover(timeout_secs: u64) -> DeskResult<Vec<(Peripheral, PeripheralProperties)>> {
         let manager = Manager::new().await?;
         let adapters = manager.adapters().await?;
 
         let central = adapters
             .into_iter()
             .next()
-            .ok_or_else(|| anyhow!("No Bluetooth adapters found"))?;
+            .ok_or(DeskError::NoAdapter)?;
 
         log::info!("Starting BLE scan for Linak desks...");
         central.start_scan(ScanFilter::default()).await?;
@@ -36,29 +97,22 @@ impl DeskController {
         let peripherals = central.peripherals().await?;
         log::info!("Found {} BLE devices", peripherals.len());
 
-        // Filter for Linak desks (they advertise the control service)
-        let mut desks = Vec::new();
+        let mut found = Vec::new();
         for peripheral in peripherals {
             if let Ok(Some(properties)) = peripheral.properties().await {
-                if let Some(name) = properties.local_name {
-                    // Linak desks usually have "Desk" or "DPG" in their name
-                    if name.to_lowercase().contains("desk")
-                        || name.to_lowercase().contains("dpg")
-                        || name.to_lowercase().contains("linak")
-                    {
-                        log::info!("Found potential Linak desk: {}", name);
-                        desks.push(peripheral);
-                    }
-                }
+                found.push((peripheral, properties));
             }
         }
 
         central.stop_scan().await?;
-        Ok(desks)
+        Ok(found)
     }
 
     /// Connect to a specific desk by address or first available desk
-    pub async fn connect(desk_address: Option<String>) -> Result<Self> {
+    ///
+    /// Heights going in or out of the controller are converted according to
+    /// `calibration`, so callers never see the desk's own readings.
+    pub async fn connect(desk_address: Option<String>, calibration: Calibration) -> DeskResult<Self> {
         // Scan for desks - do this once to find the peripheral
         let scan_duration = if desk_address.is_some() { 5u64 } else { 10u64 };
         log::info!("Scanning for desks for {} seconds...", scan_duration);
@@ -66,7 +120,7 @@ impl DeskController {
         let desks = Self::scan_for_desks(scan_duration).await?;
 
         if desks.is_empty() {
-            return Err(anyhow!("No Linak desks found"));
+            return Err(DeskError::NotFound);
         }
 
         // Find the peripheral matching the desk address
@@ -100,7 +154,7 @@ impl DeskController {
                     p
                 },
                 None => {
-                    return Err(anyhow!("Desk with address {} not found", addr));
+                    return Err(DeskError::AddressNotFound(addr.clone()));
                 }
             }
         } else {
@@ -108,7 +162,7 @@ impl DeskController {
             desks
                 .into_iter()
                 .next()
-                .ok_or_else(|| anyhow!("No desks available"))?
+                .ok_or(DeskError::NotFound)?
         };
 
         // Wait a moment after scanning to let BLE stack settle
@@ -117,24 +171,25 @@ impl DeskController {
 
         // Try to connect to the peripheral with retries (but don't rescan)
         let max_retries = 3;
-        let mut last_error = None;
-
-        for attempt in 1..=max_retries {
-            if attempt > 1 {
-                log::info!("Connection retry attempt {} of {}", attempt, max_retries);
-                // Wait longer between retries to let BLE stack settle
-                sleep(Duration::from_secs(2)).await;
-            }
+        let mut attempt = 1;
 
+        loop {
             // Try to connect to the peripheral
             log::info!("Attempting to connect to peripheral (attempt {})...", attempt);
             match Self::connect_to_peripheral(peripheral.clone()).await {
-                Ok(controller) => {
+                Ok(mut controller) => {
                     log::info!("Successfully connected on attempt {}", attempt);
+                    controller.calibration = calibration;
                     return Ok(controller);
                 }
                 Err(e) => {
                     log::error!("Connection attempt {} failed: {}", attempt, e);
+                    metrics::ble_error(BleOperation::Connect);
+
+                    // The last attempt's error is the one reported
+                    if attempt == max_retries {
+                        return Err(e);
+                    }
 
                     // Try to ensure we're disconnected before retry
                     if let Ok(true) = peripheral.is_connected().await {
@@ -142,20 +197,19 @@ impl DeskController {
                         let _ = peripheral.disconnect().await;
                         sleep(Duration::from_millis(500)).await;
                     }
-
-                    last_error = Some(e);
-                    if attempt < max_retries {
-                        log::warn!("Will retry connection...");
-                    }
+                    log::warn!("Will retry connection...");
                 }
             }
-        }
 
-        Err(last_error.unwrap_or_else(|| anyhow!("Failed to connect to desk after {} attempts", max_retries)))
+            attempt += 1;
+            log::info!("Connection retry attempt {} of {}", attempt, max_retries);
+            // Wait longer between retries to let BLE stack settle
+            sleep(Duration::from_secs(2)).await;
+        }
     }
 
     /// Connect to a specific peripheral
-    async fn connect_to_peripheral(peripheral: Peripheral) -> Result<Self> {
+    async fn connect_to_peripheral(peripheral: Peripheral) -> DeskResult<Self> {
         use tokio::time::timeout;
 
         log::info!("Entered connect_to_peripheral function");
@@ -164,8 +218,10 @@ impl DeskController {
         log::info!("Checking desk connection status...");
         let is_connected = timeout(Duration::from_secs(5), peripheral.is_connected())
             .await
-            .context("Timeout checking connection status")?
-            .context("Failed to check connection status")?;
+            .map_err(|_| DeskError::Timeout {
+                operation: "checking connection status",
+                after: Duration::from_secs(5),
+            })??;
         log::info!("Connection status check completed: is_connected = {}", is_connected);
 
         // Connect to the peripheral if not connected
@@ -177,11 +233,14 @@ impl DeskController {
                 }
                 Ok(Err(e)) => {
                     log::error!("Bluetooth connection failed: {}", e);
-                    return Err(anyhow!("Failed to connect to desk: {}", e));
+                    return Err(DeskError::ConnectionFailed(e));
                 }
                 Err(_) => {
                     log::error!("Bluetooth connection timed out after 15 seconds");
-                    return Err(anyhow!("Timeout connecting to desk (15s)"));
+                    return Err(DeskError::Timeout {
+                        operation: "connecting to desk",
+                        after: Duration::from_secs(15),
+                    });
                 }
             }
         } else {
@@ -192,8 +251,11 @@ impl DeskController {
         log::info!("Discovering desk services and characteristics...");
         timeout(Duration::from_secs(10), peripheral.discover_services())
             .await
-            .context("Timeout discovering services (10s)")?
-            .context("Failed to discover services")?;
+            .map_err(|_| DeskError::Timeout {
+                operation: "discovering services",
+                after: Duration::from_secs(10),
+            })?
+            .map_err(DeskError::ConnectionFailed)?;
         log::info!("Services discovered successfully");
 
         // Find the control service and characteristics
@@ -213,12 +275,12 @@ impl DeskController {
         if control_char.is_none() {
             log::error!("Could not find control characteristic (UUID: {})", CONTROL_CHARACTERISTIC_UUID);
             log::error!("Available characteristics: {:?}", chars.iter().map(|c| c.uuid).collect::<Vec<_>>());
-            return Err(anyhow!("Could not find control characteristic on desk"));
+            return Err(DeskError::MissingCharacteristic("control"));
         }
 
         if height_char.is_none() {
             log::error!("Could not find height characteristic (UUID: {})", HEIGHT_CHARACTERISTIC_UUID);
-            return Err(anyhow!("Could not find height characteristic on desk"));
+            return Err(DeskError::MissingCharacteristic("height"));
         }
 
         log::info!("Desk controller fully initialized and ready");
@@ -227,63 +289,126 @@ impl DeskController {
             peripheral,
             control_char,
             height_char,
+            calibration: Calibration::default(),
         })
     }
 
-    /// Get the current desk height in millimeters
-    pub async fn get_height(&self) -> Result<u16> {
+    /// Get the current desk height
+    pub async fn get_height(&self) -> DeskResult<Height> {
+        self.get_raw_height().await.map(|height| self.calibration.to_real(height))
+    }
+
+    /// Get the height as the desk reports it, ignoring the calibration
+    pub async fn get_raw_height(&self) -> DeskResult<Height> {
         let height_char = self
             .height_char
             .as_ref()
-            .ok_or_else(|| anyhow!("Height characteristic not available"))?;
+            .ok_or(DeskError::MissingCharacteristic("height"))?;
 
         log::debug!("Reading height characteristic...");
-        let data = self.peripheral.read(height_char).await
-            .context("Failed to read height characteristic from BLE")?;
+        let data = self
+            .peripheral
+            .read(height_char)
+            .await
+            .inspect_err(|_| metrics::ble_error(BleOperation::Read))?;
 
         log::info!("Read {} bytes from height characteristic: {:02X?}", data.len(), data);
 
-        let height_units = parse_height(&data)
-            .ok_or_else(|| anyhow!("Failed to parse height data from bytes: {:?}", data))?;
+        let height = parse_height(&data)
+            .ok_or_else(|| DeskError::InvalidData(data.clone()))?;
+
+        log::info!("Parsed height: {} units = {}mm (bytes: {:02X?})", height.units(), height.as_mm(), data);
+
+        Ok(height)
+    }
+
+    /// Get the current desk height and speed
+    pub async fn get_reading(&self) -> DeskResult<DeskReading> {
+        let height_char = self
+            .height_char
+            .as_ref()
+            .ok_or(DeskError::MissingCharacteristic("height"))?;
 
-        let height_mm = super::protocol::desk_units_to_mm(height_units);
-        log::info!("Parsed height: {} units = {}mm (bytes: {:02X?})", height_units, height_mm, data);
+        let data = self
+            .peripheral
+            .read(height_char)
+            .await
+            .inspect_err(|_| metrics::ble_error(BleOperation::Read))?;
 
-        Ok(height_mm)
+        parse_reading(&data)
+            .map(|reading| calibrated(reading, self.calibration))
+            .ok_or(DeskError::InvalidData(data))
+    }
+
+    /// Stream height and speed updates as the desk reports them
+    ///
+    /// Depending on the platform the stream either ends or simply goes quiet
+    /// when the connection drops, so callers should also check `is_connected`.
+    pub async fn readings(&self) -> DeskResult<impl Stream<Item = DeskReading> + Send> {
+        let height_char = self
+            .height_char
+            .as_ref()
+            .ok_or(DeskError::MissingCharacteristic("height"))?;
+
+        self.peripheral
+            .subscribe(height_char)
+            .await
+            .inspect_err(|_| metrics::ble_error(BleOperation::Subscribe))?;
+        let notifications = self.peripheral.notifications().await?;
+
+        let calibration = self.calibration;
+        Ok(notifications.filter_map(move |notification| async move {
+            match notification.uuid {
+                HEIGHT_CHARACTERISTIC_UUID => {
+                    parse_reading(&notification.value).map(|reading| calibrated(reading, calibration))
+                }
+                _ => None,
+            }
+        }))
+    }
+
+    /// Whether the Bluetooth link to the desk is still up
+    pub async fn is_connected(&self) -> bool {
+        self.peripheral.is_connected().await.unwrap_or(false)
     }
 
     /// Send a movement command to the desk
-    pub async fn send_command(&self, command: MovementCommand) -> Result<()> {
+    pub async fn send_command(&self, command: MovementCommand) -> DeskResult<()> {
         let control_char = self
             .control_char
             .as_ref()
-            .ok_or_else(|| anyhow!("Control characteristic not available"))?;
+            .ok_or(DeskError::MissingCharacteristic("control"))?;
 
+        let command = match command {
+            MovementCommand::MoveToHeight(height) => {
+                MovementCommand::MoveToHeight(self.calibration.to_reported(height))
+            }
+            command => command,
+        };
         let bytes = command.to_bytes();
         log::info!("Sending command: {:?} -> bytes: {:02X?}", command, bytes);
 
         self.peripheral
             .write(control_char, &bytes, WriteType::WithoutResponse)
             .await
-            .context("Failed to write command to BLE characteristic")?;
+            .inspect_err(|_| metrics::ble_error(BleOperation::Write))?;
 
         log::info!("Command written to BLE characteristic successfully");
 
         Ok(())
     }
 
-    /// Move desk to a specific height in millimeters
-    pub async fn move_to_height(&self, height_mm: u16) -> Result<()> {
-        let height_units = super::protocol::mm_to_desk_units(height_mm);
-        log::info!("Moving desk to {}mm ({}units)", height_mm, height_units);
+    /// Move desk to a specific height
+    pub async fn move_to_height(&self, target: Height) -> DeskResult<()> {
+        log::info!("Moving desk to {}mm ({}units)", target.as_mm(), target.units());
 
         // Get current height to determine direction
-        let current_mm = self.get_height().await?;
-        log::info!("Current height: {}mm, Target: {}mm", current_mm, height_mm);
+        let start_height = self.get_height().await?;
+        log::info!("Current height: {}mm, Target: {}mm", start_height.as_mm(), target.as_mm());
 
         // Try MoveToHeight command first
         log::info!("Attempting MoveToHeight command...");
-        self.send_command(MovementCommand::MoveToHeight(height_units))
+        self.send_command(MovementCommand::MoveToHeight(target))
             .await?;
 
         log::info!("Move command sent successfully, waiting for movement to start...");
@@ -293,21 +418,20 @@ impl DeskController {
 
         // Check if desk started moving
         let new_height = self.get_height().await?;
-        if (new_height as i32 - current_mm as i32).abs() < 5 {
+        if new_height.abs_diff(start_height) < MOVEMENT_THRESHOLD {
             log::warn!("Desk did not respond to MoveToHeight command, trying manual Up/Down...");
 
             // Desk didn't move, try using Up/Down commands instead
-            let direction = if height_mm > current_mm {
+            let direction = if target > start_height {
                 MovementCommand::Up
             } else {
                 MovementCommand::Down
             };
 
-            return self.move_manually(height_mm, direction).await;
+            return self.move_manually(target, direction).await;
         }
 
         // Poll until we reach the target height (with tolerance)
-        const TOLERANCE_MM: u16 = 5; // 5mm tolerance
         const MAX_WAIT_SECS: u64 = 30;
         const POLL_INTERVAL_MS: u64 = 200;
 
@@ -315,32 +439,31 @@ impl DeskController {
         let mut poll_count = 0;
 
         log::info!("Starting height polling (target: {}mm, tolerance: {}mm, max wait: {}s)",
-                   height_mm, TOLERANCE_MM, MAX_WAIT_SECS);
+                   target.as_mm(), TOLERANCE.as_mm(), MAX_WAIT_SECS);
 
         loop {
             poll_count += 1;
 
             if start.elapsed().as_secs() > MAX_WAIT_SECS {
                 log::error!("Timeout after {} polls and {} seconds", poll_count, MAX_WAIT_SECS);
-                return Err(anyhow!("Timeout waiting for desk to reach target height"));
+                return Err(DeskError::Timeout {
+                    operation: "waiting for desk to reach target height",
+                    after: Duration::from_secs(MAX_WAIT_SECS),
+                });
             }
 
             match self.get_height().await {
                 Ok(current) => {
-                    let diff = if current > height_mm {
-                        current - height_mm
-                    } else {
-                        height_mm - current
-                    };
+                    let diff = current.abs_diff(target);
 
                     if poll_count <= 3 || poll_count % 10 == 0 {
                         log::info!("Poll #{}: Current height: {}mm, Target: {}mm, Diff: {}mm",
-                                   poll_count, current, height_mm, diff);
+                                   poll_count, current.as_mm(), target.as_mm(), diff.as_mm());
                     }
 
-                    if diff <= TOLERANCE_MM {
+                    if diff <= TOLERANCE {
                         log::info!("Reached target height after {} polls: {}mm (target: {}mm, diff: {}mm)",
-                                   poll_count, current, height_mm, diff);
+                                   poll_count, current.as_mm(), target.as_mm(), diff.as_mm());
                         break;
                     }
                 }
@@ -349,7 +472,7 @@ impl DeskController {
                     // Continue polling despite read error - desk might still be moving
                     if poll_count > 5 {
                         log::error!("Multiple height read failures, aborting");
-                        return Err(anyhow!("Failed to read desk height: {}", e));
+                        return Err(e);
                     }
                 }
             }
@@ -361,10 +484,9 @@ impl DeskController {
     }
 
     /// Move desk manually using Up/Down commands
-    async fn move_manually(&self, target_mm: u16, direction: MovementCommand) -> Result<()> {
+    async fn move_manually(&self, target: Height, direction: MovementCommand) -> DeskResult<()> {
         log::info!("Starting manual movement using {:?} command", direction);
 
-        const TOLERANCE_MM: u16 = 5;
         const MAX_WAIT_SECS: u64 = 60;
         const POLL_INTERVAL_MS: u64 = 100;
 
@@ -378,33 +500,32 @@ impl DeskController {
             if start.elapsed().as_secs() > MAX_WAIT_SECS {
                 self.send_command(MovementCommand::Stop).await?;
                 log::error!("Manual movement timeout after {} seconds", MAX_WAIT_SECS);
-                return Err(anyhow!("Timeout during manual movement"));
+                return Err(DeskError::Timeout {
+                    operation: "during manual movement",
+                    after: Duration::from_secs(MAX_WAIT_SECS),
+                });
             }
 
             let current = self.get_height().await?;
-            let diff = if current > target_mm {
-                current - target_mm
-            } else {
-                target_mm - current
-            };
+            let diff = current.abs_diff(target);
 
             if poll_count <= 3 || poll_count % 20 == 0 {
                 log::info!("Manual move poll #{}: Current: {}mm, Target: {}mm, Diff: {}mm",
-                           poll_count, current, target_mm, diff);
+                           poll_count, current.as_mm(), target.as_mm(), diff.as_mm());
             }
 
-            if diff <= TOLERANCE_MM {
+            if diff <= TOLERANCE {
                 self.send_command(MovementCommand::Stop).await?;
-                log::info!("Manual movement complete: {}mm (target: {}mm)", current, target_mm);
+                log::info!("Manual movement complete: {}mm (target: {}mm)", current.as_mm(), target.as_mm());
                 return Ok(());
             }
 
             // Check if we're still moving
-            if (current as i32 - last_height as i32).abs() < 2 && poll_count > 10 {
+            if current.abs_diff(last_height) < STALL_THRESHOLD && poll_count > 10 {
                 // Desk has stopped moving but hasn't reached target
-                log::warn!("Desk stopped moving at {}mm, target was {}mm", current, target_mm);
+                log::warn!("Desk stopped moving at {}mm, target was {}mm", current.as_mm(), target.as_mm());
                 self.send_command(MovementCommand::Stop).await?;
-                return Err(anyhow!("Desk stopped before reaching target height"));
+                return Err(DeskError::Obstructed { stopped_at: current, target });
             }
 
             last_height = current;
@@ -418,14 +539,79 @@ impl DeskController {
         }
     }
 
+    /// Keep moving up or down for `duration`, then stop
+    pub async fn move_for(&self, direction: MovementCommand, duration: Duration) -> DeskResult<()> {
+        log::info!("Moving {:?} for {:?}", direction, duration);
+
+        // The desk only keeps moving while commands keep arriving
+        const REPEAT_INTERVAL_MS: u64 = 300;
+        let start = std::time::Instant::now();
+
+        while start.elapsed() < duration {
+            self.send_command(direction).await?;
+            let remaining = duration.saturating_sub(start.elapsed());
+            sleep(remaining.min(Duration::from_millis(REPEAT_INTERVAL_MS))).await;
+        }
+
+        self.stop().await
+    }
+
+    /// Keep moving up or down until the desk halts at its end stop
+    ///
+    /// Returns the height where it came to rest.
+    pub async fn move_to_end_stop(&self, direction: MovementCommand) -> DeskResult<Height> {
+        log::info!("Moving {:?} to the end stop", direction);
+
+        const MAX_WAIT_SECS: u64 = 60;
+        const REPEAT_INTERVAL_MS: u64 = 300;
+        // Polls without movement before the desk counts as stopped; the
+        // first commands can take a moment to get the motor going
+        const STILL_POLLS: u32 = 3;
+
+        let start = std::time::Instant::now();
+        let mut last_height = self.get_height().await?;
+        let mut still = 0;
+
+        loop {
+            if start.elapsed().as_secs() > MAX_WAIT_SECS {
+                self.stop().await?;
+                return Err(DeskError::Timeout {
+                    operation: "moving to the end stop",
+                    after: Duration::from_secs(MAX_WAIT_SECS),
+                });
+            }
+
+            self.send_command(direction).await?;
+            sleep(Duration::from_millis(REPEAT_INTERVAL_MS)).await;
+
+            let current = self.get_height().await?;
+            if current.abs_diff(last_height) < STALL_THRESHOLD {
+                still += 1;
+                if still >= STILL_POLLS {
+                    self.stop().await?;
+                    log::info!("Reached the end stop at {}mm", current.as_mm());
+                    return Ok(current);
+                }
+            } else {
+                still = 0;
+            }
+            last_height = current;
+        }
+    }
+
+    /// Bluetooth address of the connected desk
+    pub fn address(&self) -> String {
+        self.peripheral.address().to_string()
+    }
+
     /// Stop desk movement
-    pub async fn stop(&self) -> Result<()> {
+    pub async fn stop(&self) -> DeskResult<()> {
         log::info!("Stopping desk movement");
         self.send_command(MovementCommand::Stop).await
     }
 
     /// Disconnect from the desk
-    pub async fn disconnect(&self) -> Result<()> {
+    pub async fn disconnect(&self) -> DeskResult<()> {
         if self.peripheral.is_connected().await? {
             self.peripheral.disconnect().await?;
             log::info!("Disconnected from desk");
@@ -434,6 +620,14 @@ impl DeskController {
     }
 }
 
+/// A reading from the desk with its height converted to the real height
+fn calibrated(reading: DeskReading, calibration: Calibration) -> DeskReading {
+    DeskReading {
+        height: calibration.to_real(reading.height),
+        ..reading
+    }
+}
+
 impl Drop for DeskController {
     fn drop(&mut self) {
         // Best effort disconnect
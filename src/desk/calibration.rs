@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+use super::height::Height;
+
+/// Largest scale error accepted from a two-point calibration (10%)
+///
+/// Real Linak frames are off by a percent or two at most; anything beyond
+/// this is a misread tape measure rather than the desk.
+pub const MAX_SCALE_ERROR: f64 = 0.1;
+
+/// Maps the height the desk reports to its real height above the floor
+///
+/// Linak desks count from their lowest position, so what they report is off
+/// by a constant offset. Some frames also report travel slightly too short or
+/// too long, which the scale corrects.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    /// Real height in millimeters where the desk reports 0 (may be negative)
+    pub offset_mm: f64,
+
+    /// Real travel per reported travel; 1.0 when the desk measures correctly
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+impl Default for Calibration {
+    /// Trust the desk's readings as they are
+    fn default() -> Self {
+        Self {
+            offset_mm: 0.0,
+            scale: default_scale(),
+        }
+    }
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Calibration {
+    /// Calibrate from one tape measurement, trusting the desk's scale
+    pub fn from_point(reported: Height, measured: Height) -> Self {
+        Self {
+            offset_mm: measured.as_mm() - reported.as_mm(),
+            scale: default_scale(),
+        }
+    }
+
+    /// Calibrate from measurements at two heights, which also reveals a scale error
+    ///
+    /// Returns `None` if the points are too close together to tell, or the
+    /// scale error is beyond [`MAX_SCALE_ERROR`].
+    pub fn from_points(low: (Height, Height), high: (Height, Height)) -> Option<Self> {
+        let reported_travel = high.0.as_mm() - low.0.as_mm();
+        let measured_travel = high.1.as_mm() - low.1.as_mm();
+        if reported_travel.abs() < 10.0 {
+            return None;
+        }
+
+        let scale = measured_travel / reported_travel;
+        if (scale - 1.0).abs() > MAX_SCALE_ERROR {
+            return None;
+        }
+
+        Some(Self {
+            offset_mm: low.1.as_mm() - low.0.as_mm() * scale,
+            scale,
+        })
+    }
+
+    /// How far off the desk's own distances are (0.012 = reports 1.2% too little)
+    pub fn scale_error(&self) -> f64 {
+        self.scale - 1.0
+    }
+
+    /// Real height for a height reported by the desk
+    pub fn to_real(self, reported: Height) -> Height {
+        saturating_height(reported.as_mm() * self.scale + self.offset_mm)
+    }
+
+    /// Height the desk has to be sent to end up at `real`
+    pub fn to_reported(self, real: Height) -> Height {
+        saturating_height((real.as_mm() - self.offset_mm) / self.scale)
+    }
+}
+
+/// Clamp a computed millimeter value into the range a `Height` can hold
+fn saturating_height(mm: f64) -> Height {
+    let max_mm = Height::from_units(u16::MAX).as_mm();
+    Height::from_mm_f64(mm.clamp(0.0, max_mm)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mm(mm: u16) -> Height {
+        Height::from_mm(mm).unwrap()
+    }
+
+    #[test]
+    fn test_single_point_offset() {
+        let calibration = Calibration::from_point(mm(0), mm(620));
+        assert_eq!(calibration.offset_mm, 620.0);
+        assert_eq!(calibration.to_real(mm(430)), mm(1050));
+        assert_eq!(calibration.to_reported(mm(1050)), mm(430));
+
+        // Targets below the desk's zero can't be sent
+        assert_eq!(calibration.to_reported(mm(500)), mm(0));
+    }
+
+    #[test]
+    fn test_two_points_detect_scale_error() {
+        // The desk reports 400mm of travel where the tape shows 404mm
+        let calibration = Calibration::from_points((mm(0), mm(620)), (mm(400), mm(1024))).unwrap();
+        assert!((calibration.scale_error() - 0.01).abs() < 1e-9);
+        assert_eq!(calibration.to_real(mm(400)), mm(1024));
+        assert_eq!(calibration.to_reported(mm(1024)), mm(400));
+
+        // Too close together, or wildly inconsistent measurements
+        assert!(Calibration::from_points((mm(0), mm(620)), (mm(5), mm(625))).is_none());
+        assert!(Calibration::from_points((mm(0), mm(620)), (mm(400), mm(1320))).is_none());
+    }
+
+    #[test]
+    fn test_default_is_identity() {
+        let calibration = Calibration::default();
+        assert_eq!(calibration.to_real(Height::from_units(10505)), Height::from_units(10505));
+        assert_eq!(calibration.to_reported(Height::from_units(10505)), Height::from_units(10505));
+    }
+}
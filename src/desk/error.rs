@@ -0,0 +1,190 @@
+use std::time::Duration;
+use thiserror::Error;
+
+use super::height::Height;
+
+pub type DeskResult<T> = Result<T, DeskError>;
+
+/// Everything that can go wrong while finding, connecting to or moving a desk
+#[derive(Debug, Error)]
+pub enum DeskError {
+    #[error("No desk configured. Run `desk-control connect` or use \"Configure Desk...\" in the tray.")]
+    NotConfigured,
+
+    #[error("No Bluetooth adapters found")]
+    NoAdapter,
+
+    #[error("No Linak desks found")]
+    NotFound,
+
+    #[error("Desk with address {0} not found")]
+    AddressNotFound(String),
+
+    #[error("Failed to connect to desk: {0}")]
+    ConnectionFailed(#[source] btleplug::Error),
+
+    #[error("Timeout {operation} ({}s)", .after.as_secs())]
+    Timeout {
+        operation: &'static str,
+        after: Duration,
+    },
+
+    #[error("Could not find {0} characteristic on desk")]
+    MissingCharacteristic(&'static str),
+
+    #[error("Failed to parse height data from bytes: {0:02X?}")]
+    InvalidData(Vec<u8>),
+
+    #[error("Desk stopped at {stopped_at} before reaching {target}")]
+    Obstructed { stopped_at: Height, target: Height },
+
+    #[error("Height {height} is below the minimum of {min}")]
+    BelowMinimum { height: Height, min: Height },
+
+    #[error("Height {height} is above the maximum of {max}")]
+    AboveMaximum { height: Height, max: Height },
+
+    #[error("{0}")]
+    InvalidTarget(String),
+
+    #[error("Bluetooth error: {0}")]
+    Bluetooth(#[from] btleplug::Error),
+
+    #[error("Movement was interrupted by another command")]
+    Interrupted,
+
+    #[error("desk-control daemon: {0}")]
+    Daemon(String),
+
+    /// An error reported by the daemon on behalf of a client
+    #[error("{message}")]
+    Remote {
+        message: String,
+        exit_code: i32,
+        user_message: String,
+    },
+}
+
+/// Process exit codes for failed `desk-control` subcommands
+///
+/// 0 means success and 2 is used by clap for invalid arguments.
+pub mod exit_code {
+    /// Any error not listed below (config file problems, bad input, ...)
+    pub const FAILURE: i32 = 1;
+    pub const NOT_CONFIGURED: i32 = 3;
+    pub const NOT_FOUND: i32 = 4;
+    pub const BLUETOOTH: i32 = 5;
+    pub const CONNECTION: i32 = 6;
+    pub const TIMEOUT: i32 = 7;
+    pub const OBSTRUCTED: i32 = 8;
+    pub const OUT_OF_RANGE: i32 = 9;
+}
+
+impl DeskError {
+    /// Exit code reported by the CLI when a command fails with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::NotConfigured => exit_code::NOT_CONFIGURED,
+            Self::NotFound | Self::AddressNotFound(_) => exit_code::NOT_FOUND,
+            Self::NoAdapter | Self::Bluetooth(_) => exit_code::BLUETOOTH,
+            Self::ConnectionFailed(_)
+            | Self::MissingCharacteristic(_)
+            | Self::InvalidData(_)
+            | Self::Daemon(_) => exit_code::CONNECTION,
+            Self::Timeout { .. } => exit_code::TIMEOUT,
+            Self::Obstructed { .. } | Self::Interrupted => exit_code::OBSTRUCTED,
+            Self::BelowMinimum { .. } | Self::AboveMaximum { .. } | Self::InvalidTarget(_) => {
+                exit_code::OUT_OF_RANGE
+            }
+            Self::Remote { exit_code, .. } => *exit_code,
+        }
+    }
+
+    /// Text for desktop notifications: what happened and what to try
+    pub fn user_message(&self) -> String {
+        match self {
+            Self::NotConfigured => {
+                "No desk is configured yet. Use \"Configure Desk...\" to choose one.".to_string()
+            }
+            Self::NoAdapter => {
+                "No Bluetooth adapter found. Check that Bluetooth is turned on.".to_string()
+            }
+            Self::NotFound => {
+                "No desk found nearby. Make sure it is powered on and in range.".to_string()
+            }
+            Self::AddressNotFound(address) => format!(
+                "Your desk ({}) could not be found. Make sure it is powered on and in range.",
+                address
+            ),
+            Self::ConnectionFailed(_) => {
+                "Could not connect to the desk. Try again, or restart Bluetooth if this keeps happening."
+                    .to_string()
+            }
+            Self::Timeout { operation, .. } => {
+                format!("The desk took too long to respond ({}).", operation)
+            }
+            Self::MissingCharacteristic(_) | Self::InvalidData(_) => {
+                "This doesn't look like a supported Linak desk.".to_string()
+            }
+            Self::Obstructed { stopped_at, target } => format!(
+                "The desk stopped at {} before reaching {}. Check for obstructions.",
+                stopped_at, target
+            ),
+            Self::BelowMinimum { .. } | Self::AboveMaximum { .. } | Self::InvalidTarget(_) => {
+                format!("{}.", self)
+            }
+            Self::Bluetooth(e) => format!("Bluetooth error: {}", e),
+            Self::Interrupted => "The desk stopped because another command took over.".to_string(),
+            Self::Daemon(_) => {
+                "Lost contact with the desk-control daemon. Check that it is still running.".to_string()
+            }
+            Self::Remote { user_message, .. } => user_message.clone(),
+        }
+    }
+}
+
+/// Exit code for a failed CLI command, looking for a `DeskError` in the chain
+pub fn exit_code_for(error: &anyhow::Error) -> i32 {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<DeskError>())
+        .map_or(exit_code::FAILURE, DeskError::exit_code)
+}
+
+/// Notification text for any error, friendlier when it came from the desk
+pub fn user_message_for(error: &anyhow::Error) -> String {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<DeskError>())
+        .map_or_else(|| error.to_string(), DeskError::user_message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_exit_codes() {
+        let obstructed = DeskError::Obstructed {
+            stopped_at: Height::from_units(9000),
+            target: Height::from_units(10500),
+        };
+        assert_eq!(obstructed.exit_code(), exit_code::OBSTRUCTED);
+        assert_eq!(obstructed.to_string(), "Desk stopped at 90.0cm before reaching 105.0cm");
+
+        let wrapped = Err::<(), _>(DeskError::NotFound).context("Failed to move desk");
+        assert_eq!(exit_code_for(&wrapped.unwrap_err()), exit_code::NOT_FOUND);
+
+        assert_eq!(exit_code_for(&anyhow::anyhow!("bad config")), exit_code::FAILURE);
+    }
+
+    #[test]
+    fn test_timeout_message() {
+        let error = DeskError::Timeout {
+            operation: "connecting to desk",
+            after: Duration::from_secs(15),
+        };
+        assert_eq!(error.to_string(), "Timeout connecting to desk (15s)");
+    }
+}
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+use crate::units::{parse_height, DisplayUnit};
+
+/// Desk units per millimeter (the desk reports height in 0.1mm steps)
+const UNITS_PER_MM: u16 = 10;
+
+/// A desk height, stored in the desk's native 0.1mm units
+///
+/// All conversions from millimeters are checked, so a height can never wrap
+/// around or silently lose the desk's sub-millimetre resolution.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Height(u16);
+
+impl Height {
+    /// Create a height from raw desk units (0.1mm)
+    pub const fn from_units(units: u16) -> Self {
+        Self(units)
+    }
+
+    /// Create a height from whole millimeters, or `None` if it doesn't fit
+    pub fn from_mm(mm: u16) -> Option<Self> {
+        mm.checked_mul(UNITS_PER_MM).map(Self)
+    }
+
+    /// Create a height from fractional millimeters, rounded to 0.1mm
+    ///
+    /// Returns `None` for negative, non-finite or too large values.
+    pub fn from_mm_f64(mm: f64) -> Option<Self> {
+        let units = (mm * UNITS_PER_MM as f64).round();
+        if units.is_finite() && (0.0..=u16::MAX as f64).contains(&units) {
+            Some(Self(units as u16))
+        } else {
+            None
+        }
+    }
+
+    /// Raw desk units (0.1mm) as sent over BLE
+    pub const fn units(self) -> u16 {
+        self.0
+    }
+
+    /// Height in millimeters, keeping the 0.1mm fraction
+    pub fn as_mm(self) -> f64 {
+        self.0 as f64 / UNITS_PER_MM as f64
+    }
+
+    /// Absolute difference between two heights
+    pub fn abs_diff(self, other: Self) -> Self {
+        Self(self.0.abs_diff(other.0))
+    }
+
+    /// Format this height in the given display unit
+    pub fn display(self, unit: DisplayUnit) -> String {
+        unit.format(self)
+    }
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.display(DisplayUnit::default()))
+    }
+}
+
+/// Serialized as millimeters: an integer when whole (1050), otherwise with
+/// one decimal (1050.5), so existing config files keep their format
+impl Serialize for Height {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 % UNITS_PER_MM {
+            0 => serializer.serialize_u16(self.0 / UNITS_PER_MM),
+            _ => serializer.serialize_f64(self.as_mm()),
+        }
+    }
+}
+
+/// Accepts a number of millimeters or a string with a unit ("105cm", "41.5in")
+impl<'de> Deserialize<'de> for Height {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawHeight {
+            Millimeters(f64),
+            Text(String),
+        }
+
+        match RawHeight::deserialize(deserializer)? {
+            RawHeight::Millimeters(mm) => Height::from_mm_f64(mm).ok_or_else(|| {
+                serde::de::Error::custom(format!("height {}mm is out of range", mm))
+            }),
+            RawHeight::Text(text) => {
+                parse_height(&text, DisplayUnit::Mm).map_err(serde::de::Error::custom)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checked_conversions() {
+        assert_eq!(Height::from_mm(1050), Some(Height::from_units(10500)));
+        assert_eq!(Height::from_mm(6553), Some(Height::from_units(65530)));
+        assert_eq!(Height::from_mm(6554), None);
+
+        assert_eq!(Height::from_mm_f64(1050.46), Some(Height::from_units(10505)));
+        assert_eq!(Height::from_mm_f64(-1.0), None);
+        assert_eq!(Height::from_mm_f64(f64::NAN), None);
+        assert_eq!(Height::from_mm_f64(7000.0), None);
+    }
+
+    #[test]
+    fn test_keeps_sub_millimetre_resolution() {
+        let height = Height::from_units(10507);
+        assert_eq!(height.as_mm(), 1050.7);
+        assert_eq!(height.abs_diff(Height::from_units(10500)).units(), 7);
+        assert_eq!(height.to_string(), "105.1cm");
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let whole = Height::from_mm(1050).unwrap();
+        assert_eq!(serde_json::to_string(&whole).unwrap(), "1050");
+
+        let fractional = Height::from_units(10505);
+        assert_eq!(serde_json::to_string(&fractional).unwrap(), "1050.5");
+        assert_eq!(serde_json::from_str::<Height>("1050.5").unwrap(), fractional);
+
+        assert_eq!(serde_json::from_str::<Height>("\"105cm\"").unwrap(), whole);
+        assert!(serde_json::from_str::<Height>("-3").is_err());
+        assert!(serde_json::from_str::<Height>("\"tall\"").is_err());
+    }
+}
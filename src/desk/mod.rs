@@ -1,5 +1,14 @@
 pub mod bluetooth;
+pub mod calibration;
+pub mod error;
+pub mod height;
 pub mod protocol;
+pub mod scan;
+pub mod watch;
 
 pub use bluetooth::DeskController;
+pub use calibration::Calibration;
+pub use error::{DeskError, DeskResult};
+pub use height::Height;
 pub use protocol::MovementCommand;
+pub use scan::ScanReport;
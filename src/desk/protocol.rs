@@ -1,5 +1,7 @@
 use uuid::Uuid;
 
+use super::height::Height;
+
 /// Linak BLE Service and Characteristic UUIDs
 /// Based on reverse engineering of Linak DPG (Desk Panel Gateway) protocol
 
@@ -20,7 +22,7 @@ pub const REFERENCE_INPUT_UUID: Uuid =
     Uuid::from_u128(0x99fa0031_338a_1024_8a49_009c0215f78a);
 
 /// Movement commands
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MovementCommand {
     /// Stop all movement
     Stop,
@@ -28,8 +30,8 @@ pub enum MovementCommand {
     Up,
     /// Move desk down
     Down,
-    /// Move to specific height (in 0.1mm units)
-    MoveToHeight(u16),
+    /// Move to specific height
+    MoveToHeight(Height),
 }
 
 impl MovementCommand {
@@ -42,7 +44,7 @@ impl MovementCommand {
             Self::MoveToHeight(height) => {
                 // Move to position command
                 // Format: [0x05, low_byte, high_byte]
-                let height_bytes = height.to_le_bytes();
+                let height_bytes = height.units().to_le_bytes();
                 vec![0x05, height_bytes[0], height_bytes[1]]
             }
         }
@@ -51,22 +53,38 @@ impl MovementCommand {
 
 /// Parse height from BLE characteristic data
 /// Height is transmitted as 16-bit little-endian in 0.1mm units
-pub fn parse_height(data: &[u8]) -> Option<u16> {
+pub fn parse_height(data: &[u8]) -> Option<Height> {
     if data.len() >= 2 {
-        Some(u16::from_le_bytes([data[0], data[1]]))
+        Some(Height::from_units(u16::from_le_bytes([data[0], data[1]])))
     } else {
         None
     }
 }
 
-/// Convert millimeters to the desk's internal format (0.1mm units)
-pub fn mm_to_desk_units(mm: u16) -> u16 {
-    mm * 10
+/// Height and speed as reported by the height characteristic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeskReading {
+    pub height: Height,
+    /// Signed speed as reported by the desk: positive moving up, 0 when still
+    pub speed: i16,
+}
+
+impl DeskReading {
+    pub fn is_moving(&self) -> bool {
+        self.speed != 0
+    }
 }
 
-/// Convert desk's internal format (0.1mm units) to millimeters
-pub fn desk_units_to_mm(units: u16) -> u16 {
-    units / 10
+/// Parse height and speed from BLE characteristic data
+/// Bytes 0-1 are the height, bytes 2-3 the signed speed (both little-endian).
+/// Desks that only send the height are reported as not moving.
+pub fn parse_reading(data: &[u8]) -> Option<DeskReading> {
+    let height = parse_height(data)?;
+    let speed = match data {
+        [_, _, low, high, ..] => i16::from_le_bytes([*low, *high]),
+        _ => 0,
+    };
+    Some(DeskReading { height, speed })
 }
 
 #[cfg(test)]
@@ -79,19 +97,31 @@ mod tests {
         assert_eq!(MovementCommand::Up.to_bytes(), vec![0x47, 0x00]);
         assert_eq!(MovementCommand::Down.to_bytes(), vec![0x46, 0x00]);
 
-        let height = MovementCommand::MoveToHeight(10500); // 1050mm = 105cm
+        let height = MovementCommand::MoveToHeight(Height::from_units(10500)); // 1050mm = 105cm
         assert_eq!(height.to_bytes(), vec![0x05, 0x04, 0x29]);
     }
 
     #[test]
     fn test_height_conversion() {
-        assert_eq!(mm_to_desk_units(1050), 10500);
-        assert_eq!(desk_units_to_mm(10500), 1050);
+        assert_eq!(Height::from_mm(1050).map(Height::units), Some(10500));
+        assert_eq!(Height::from_units(10500).as_mm(), 1050.0);
+        assert_eq!(Height::from_units(10507).as_mm(), 1050.7);
     }
 
     #[test]
     fn test_parse_height() {
-        assert_eq!(parse_height(&[0x04, 0x29]), Some(10500));
+        assert_eq!(parse_height(&[0x04, 0x29]), Some(Height::from_units(10500)));
         assert_eq!(parse_height(&[0x00]), None);
     }
+
+    #[test]
+    fn test_parse_reading() {
+        let reading = parse_reading(&[0x04, 0x29, 0x9c, 0xff]).unwrap();
+        assert_eq!(reading.height, Height::from_units(10500));
+        assert_eq!(reading.speed, -100);
+        assert!(reading.is_moving());
+
+        assert_eq!(parse_reading(&[0x04, 0x29]).map(|r| r.speed), Some(0));
+        assert_eq!(parse_reading(&[0x04]), None);
+    }
 }
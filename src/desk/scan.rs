@@ -0,0 +1,179 @@
+use btleplug::api::PeripheralProperties;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use super::protocol::CONTROL_SERVICE_UUID;
+
+/// Name fragments used by Linak desks that don't advertise their services
+const LINAK_NAME_HINTS: [&str; 3] = ["desk", "dpg", "linak"];
+
+/// Desk protocol recognised from a device's advertisement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeskProtocol {
+    /// Linak DPG (Desk Panel Gateway), spoken by `DeskController`
+    Linak,
+}
+
+impl DeskProtocol {
+    /// Vendor name for display
+    pub fn vendor(&self) -> &'static str {
+        match self {
+            Self::Linak => "Linak",
+        }
+    }
+}
+
+/// How a device was recognised as a desk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Detection {
+    /// It advertises one of the protocol's services (reliable)
+    Service,
+    /// Only its name looks like a desk (may be a false positive)
+    Name,
+}
+
+/// Everything learned about one BLE device during a scan
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanReport {
+    pub address: String,
+    pub name: Option<String>,
+    /// Signal strength in dBm; higher (closer to 0) is nearer
+    pub rssi: Option<i16>,
+    pub tx_power: Option<i16>,
+    pub services: Vec<Uuid>,
+    /// Manufacturer data by company ID, hex-encoded
+    pub manufacturer_data: BTreeMap<u16, String>,
+    pub protocol: Option<DeskProtocol>,
+    pub vendor: Option<&'static str>,
+    pub detected_by: Option<Detection>,
+    /// Whether this is the desk in the config file
+    pub configured: bool,
+}
+
+impl ScanReport {
+    /// Build a report from a peripheral's advertised properties
+    pub fn from_properties(props: &PeripheralProperties, configured_address: Option<&str>) -> Self {
+        let address = props.address.to_string();
+        let detected = detect(props.local_name.as_deref(), &props.services);
+
+        let mut services = props.services.clone();
+        services.sort();
+
+        Self {
+            configured: configured_address.is_some_and(|c| c.eq_ignore_ascii_case(&address)),
+            address,
+            name: props.local_name.clone(),
+            rssi: props.rssi,
+            tx_power: props.tx_power_level,
+            services,
+            manufacturer_data: props
+                .manufacturer_data
+                .iter()
+                .map(|(id, data)| (*id, to_hex(data)))
+                .collect(),
+            protocol: detected.map(|(protocol, _)| protocol),
+            vendor: detected.map(|(protocol, _)| protocol.vendor()),
+            detected_by: detected.map(|(_, detection)| detection),
+        }
+    }
+
+    /// Whether the device was recognised as a desk
+    pub fn is_desk(&self) -> bool {
+        self.protocol.is_some()
+    }
+
+    /// Sort key putting desks first, service matches before name matches,
+    /// then the nearest (strongest signal) first
+    pub fn relevance(&self) -> (bool, bool, Reverse<i16>) {
+        (
+            !self.is_desk(),
+            self.detected_by != Some(Detection::Service),
+            Reverse(self.rssi.unwrap_or(i16::MIN)),
+        )
+    }
+}
+
+/// Recognise a desk from its advertised name and services
+pub fn detect(name: Option<&str>, services: &[Uuid]) -> Option<(DeskProtocol, Detection)> {
+    if services.iter().any(is_linak_service) {
+        return Some((DeskProtocol::Linak, Detection::Service));
+    }
+
+    let name = name?.to_lowercase();
+    LINAK_NAME_HINTS
+        .iter()
+        .any(|hint| name.contains(hint))
+        .then_some((DeskProtocol::Linak, Detection::Name))
+}
+
+/// Linak services all look like 99faXXXX-338a-1024-8a49-009c0215f78a
+fn is_linak_service(uuid: &Uuid) -> bool {
+    const BASE_MASK: u128 = !(0xffff_u128 << 96);
+    uuid.as_u128() & BASE_MASK == CONTROL_SERVICE_UUID.as_u128() & BASE_MASK
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(name: &str, rssi: Option<i16>, services: Vec<Uuid>) -> ScanReport {
+        let detected = detect(Some(name), &services);
+        ScanReport {
+            address: name.to_string(),
+            name: Some(name.to_string()),
+            rssi,
+            tx_power: None,
+            services,
+            manufacturer_data: BTreeMap::new(),
+            protocol: detected.map(|(p, _)| p),
+            vendor: detected.map(|(p, _)| p.vendor()),
+            detected_by: detected.map(|(_, d)| d),
+            configured: false,
+        }
+    }
+
+    #[test]
+    fn test_detects_linak_by_service() {
+        let position_service = Uuid::from_u128(0x99fa0020_338a_1024_8a49_009c0215f78a);
+        assert_eq!(
+            detect(Some("Office"), &[position_service]),
+            Some((DeskProtocol::Linak, Detection::Service))
+        );
+        assert_eq!(
+            detect(None, &[CONTROL_SERVICE_UUID]),
+            Some((DeskProtocol::Linak, Detection::Service))
+        );
+    }
+
+    #[test]
+    fn test_detects_linak_by_name() {
+        assert_eq!(
+            detect(Some("Desk 4412"), &[]),
+            Some((DeskProtocol::Linak, Detection::Name))
+        );
+        assert_eq!(detect(Some("JBL Flip"), &[]), None);
+        assert_eq!(detect(None, &[Uuid::from_u128(0x180f)]), None);
+    }
+
+    #[test]
+    fn test_sort_puts_nearest_desk_first() {
+        let mut reports = [
+            report("Speaker", Some(-30), vec![]),
+            report("Desk far", Some(-80), vec![CONTROL_SERVICE_UUID]),
+            report("Desk near", Some(-50), vec![CONTROL_SERVICE_UUID]),
+            report("Desk by name", Some(-40), vec![]),
+        ];
+        reports.sort_by_key(ScanReport::relevance);
+
+        let names: Vec<_> = reports.iter().map(|r| r.address.as_str()).collect();
+        assert_eq!(names, ["Desk near", "Desk far", "Desk by name", "Speaker"]);
+    }
+}
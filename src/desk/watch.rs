@@ -0,0 +1,162 @@
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::time::interval;
+
+use super::bluetooth::DeskController;
+use super::calibration::Calibration;
+use super::height::Height;
+use super::protocol::DeskReading;
+use crate::backoff::{self, Backoff};
+use crate::config::DrinkSize;
+
+/// How often to check that the link is still up while the desk is quiet
+const LINK_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Poll interval when the desk doesn't support height notifications
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A change observed while watching the desk
+///
+/// The `move_*` events are only sent by the daemon, which knows about the
+/// moves its clients ask for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WatchEvent {
+    Connecting { address: String },
+    Connected { address: String },
+    Height {
+        height_mm: Height,
+        speed: i16,
+        moving: bool,
+    },
+    Disconnected { error: String, retry_in_secs: u64 },
+    MoveStarted {
+        target_mm: Height,
+        /// Set when the move is to a preset
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        preset: Option<DrinkSize>,
+    },
+    MoveFinished { target_mm: Height, height_mm: Height },
+    MoveFailed { target_mm: Height, error: String },
+}
+
+impl From<DeskReading> for WatchEvent {
+    fn from(reading: DeskReading) -> Self {
+        Self::Height {
+            height_mm: reading.height,
+            speed: reading.speed,
+            moving: reading.is_moving(),
+        }
+    }
+}
+
+/// Follow the desk at `address`, sending every change to `events`
+///
+/// Heights are converted with `calibration`. Reconnects with a growing delay
+/// whenever the link drops. Returns once the receiving side of `events` is
+/// dropped, after disconnecting from the desk.
+pub async fn watch(address: String, calibration: Calibration, events: UnboundedSender<WatchEvent>) {
+    let mut retry = Backoff::new(backoff::DESK);
+
+    loop {
+        if events
+            .send(WatchEvent::Connecting { address: address.clone() })
+            .is_err()
+        {
+            return;
+        }
+
+        let connected = tokio::select! {
+            result = DeskController::connect(Some(address.clone()), calibration) => result,
+            _ = events.closed() => return,
+        };
+
+        let error = match connected {
+            Ok(desk) => {
+                retry.reset();
+                let _ = events.send(WatchEvent::Connected { address: address.clone() });
+
+                let result = follow(&desk, &events).await;
+                if events.is_closed() {
+                    if let Err(e) = desk.disconnect().await {
+                        log::debug!("Failed to disconnect cleanly: {}", e);
+                    }
+                    return;
+                }
+                match result {
+                    Ok(()) => anyhow!("Desk disconnected"),
+                    Err(e) => e,
+                }
+            }
+            Err(e) => e.into(),
+        };
+
+        log::warn!("Lost desk connection: {}", error);
+        let _ = events.send(WatchEvent::Disconnected {
+            error: error.to_string(),
+            retry_in_secs: retry.delay().as_secs(),
+        });
+
+        tokio::select! {
+            _ = retry.wait() => {}
+            _ = events.closed() => return,
+        }
+    }
+}
+
+/// Forward readings until the link drops or nobody is listening any more
+pub async fn follow(desk: &DeskController, events: &UnboundedSender<WatchEvent>) -> Result<()> {
+    let mut last = desk.get_reading().await?;
+    let _ = events.send(last.into());
+
+    let mut readings = match desk.readings().await {
+        Ok(readings) => Box::pin(readings),
+        Err(e) => {
+            log::warn!("Height notifications unavailable ({}), polling instead", e);
+            return poll(desk, events, last).await;
+        }
+    };
+
+    let mut link_check = interval(LINK_CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            reading = readings.next() => {
+                let reading = reading.ok_or_else(|| anyhow!("Desk stopped sending updates"))?;
+                if reading != last {
+                    last = reading;
+                    let _ = events.send(reading.into());
+                }
+            }
+            _ = link_check.tick() => {
+                if !desk.is_connected().await {
+                    return Err(anyhow!("Desk disconnected"));
+                }
+            }
+            _ = events.closed() => return Ok(()),
+        }
+    }
+}
+
+/// Fallback for desks without notifications: read the height periodically
+async fn poll(
+    desk: &DeskController,
+    events: &UnboundedSender<WatchEvent>,
+    mut last: DeskReading,
+) -> Result<()> {
+    let mut ticker = interval(POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let reading = desk.get_reading().await?;
+                if reading != last {
+                    last = reading;
+                    let _ = events.send(reading.into());
+                }
+            }
+            _ = events.closed() => return Ok(()),
+        }
+    }
+}
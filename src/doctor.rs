@@ -0,0 +1,364 @@
+use anyhow::Result;
+use btleplug::api::{Central, CentralState, Manager as _};
+use btleplug::platform::Manager;
+use serde::Serialize;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::daemon::service::Status as DaemonStatus;
+use crate::daemon::DaemonClient;
+use crate::desk::{DeskController, DeskError};
+
+/// How long to wait for the desk before declaring it unreachable
+const DESK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    /// Not necessarily broken, but worth a look
+    Warn,
+    Fail,
+    /// Not applicable here, or blocked by an earlier failure
+    Skip,
+}
+
+impl Status {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Pass => "PASS",
+            Self::Warn => "WARN",
+            Self::Fail => "FAIL",
+            Self::Skip => "SKIP",
+        }
+    }
+}
+
+/// One line of the doctor report
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// What to do about a warning or failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+/// Run every check in order; later checks are skipped when they can't work
+pub async fn run_checks() -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    #[cfg(target_os = "linux")]
+    {
+        checks.push(linux::check_bluez());
+        checks.push(linux::check_bluetooth_group());
+    }
+
+    let adapter = check_adapter().await;
+    let adapter_ok = adapter.status == Status::Pass;
+    checks.push(adapter);
+
+    #[cfg(target_os = "linux")]
+    checks.push(linux::check_tray_host());
+
+    let (config_check, config) = check_config();
+    checks.push(config_check);
+
+    checks.push(match config {
+        Some(config) if adapter_ok => check_desk(&config).await,
+        Some(_) => Check::new("desk", Status::Skip, "No working Bluetooth adapter"),
+        None => Check::new("desk", Status::Skip, "Configuration is not usable"),
+    });
+
+    checks
+}
+
+/// Print a human-readable report with hints
+pub fn print_report(checks: &[Check]) {
+    for check in checks {
+        println!("[{}] {:<10} {}", check.status.label(), check.name, check.detail);
+        if let Some(hint) = &check.hint {
+            println!("       {:<10} -> {}", "", hint);
+        }
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    match failed {
+        0 => println!("\nEverything needed to control the desk looks fine."),
+        n => println!("\n{} check(s) failed, see the hints above.", n),
+    }
+}
+
+/// Whether any check failed
+pub fn has_failures(checks: &[Check]) -> bool {
+    checks.iter().any(|check| check.status == Status::Fail)
+}
+
+/// Run the checks and print them as text or JSON, returning whether all passed
+pub fn run(json: bool) -> Result<bool> {
+    let checks = tokio::runtime::Runtime::new()?.block_on(run_checks());
+
+    if json {
+        println!("{}", serde_json::to_string(&checks)?);
+    } else {
+        print_report(&checks);
+    }
+
+    Ok(!has_failures(&checks))
+}
+
+async fn check_adapter() -> Check {
+    let adapters = match Manager::new().await {
+        Ok(manager) => manager.adapters().await,
+        Err(e) => Err(e),
+    };
+
+    let adapter = match adapters.map(|adapters| adapters.into_iter().next()) {
+        Ok(Some(adapter)) => adapter,
+        Ok(None) => {
+            return Check::new("adapter", Status::Fail, "No Bluetooth adapter found")
+                .hint("Plug in or enable a Bluetooth adapter (check `rfkill list` on Linux)")
+        }
+        Err(e) => {
+            return Check::new("adapter", Status::Fail, format!("Bluetooth unavailable: {}", e))
+                .hint("Make sure the Bluetooth service is running")
+        }
+    };
+
+    let info = adapter.adapter_info().await.unwrap_or_else(|_| "adapter".to_string());
+    match adapter.adapter_state().await {
+        Ok(CentralState::PoweredOn) => Check::new("adapter", Status::Pass, format!("{} is powered on", info)),
+        Ok(CentralState::PoweredOff) => {
+            Check::new("adapter", Status::Fail, format!("{} is powered off", info))
+                .hint("Turn Bluetooth on (`bluetoothctl power on` on Linux)")
+        }
+        Ok(CentralState::Unknown) | Err(_) => {
+            Check::new("adapter", Status::Warn, format!("Could not tell whether {} is powered on", info))
+        }
+    }
+}
+
+fn check_config() -> (Check, Option<Config>) {
+    let path = Config::config_file()
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| "~/.desk-control/config".to_string());
+
+    match Config::read_existing() {
+        Ok(None) => (
+            Check::new("config", Status::Warn, format!("{} does not exist yet", path))
+                .hint("It is created with defaults on first start"),
+            Some(Config::default()),
+        ),
+        Ok(Some(config)) => match config.validate() {
+            Ok(()) => (Check::new("config", Status::Pass, format!("{} is valid", path)), Some(config)),
+            Err(e) => (
+                Check::new("config", Status::Fail, format!("{:#}", e))
+                    .hint(format!("Fix {} or run `desk-control config restore`", path)),
+                None,
+            ),
+        },
+        Err(e) => (
+            Check::new("config", Status::Fail, format!("{:#}", e))
+                .hint("Run `desk-control config restore` to go back to a backup"),
+            None,
+        ),
+    }
+}
+
+async fn check_desk(config: &Config) -> Check {
+    let Some(address) = config.desk_address.clone() else {
+        return Check::new("desk", Status::Warn, "No desk configured")
+            .hint("Run `desk-control scan` and `desk-control connect <address>`");
+    };
+
+    // A running daemon holds the link, so ask it instead of connecting again
+    if let Some(client) = DaemonClient::find().await {
+        return match client.status().await {
+            Ok(DaemonStatus {
+                height_mm: Some(height),
+                ..
+            }) => Check::new(
+                "desk",
+                Status::Pass,
+                format!("{} answered at {} (through the daemon)", address, config.format_height(height)),
+            ),
+            Ok(status) => Check::new(
+                "desk",
+                Status::Fail,
+                status.error.unwrap_or_else(|| "The daemon is not connected to the desk".to_string()),
+            )
+            .hint("Check that the desk is powered on and in range; the daemon keeps retrying"),
+            Err(e) => Check::new("desk", Status::Fail, e.to_string()).hint(e.user_message()),
+        };
+    }
+
+    let reading = tokio::time::timeout(DESK_TIMEOUT, async {
+        let desk = DeskController::connect(Some(address.clone()), config.calibration()).await?;
+        desk.get_height().await
+    })
+    .await
+    .unwrap_or(Err(DeskError::Timeout {
+        operation: "reaching the desk",
+        after: DESK_TIMEOUT,
+    }));
+
+    match reading {
+        Ok(height) => Check::new(
+            "desk",
+            Status::Pass,
+            format!("{} answered at {}", address, config.format_height(height)),
+        ),
+        Err(e) => Check::new("desk", Status::Fail, e.to_string()).hint(e.user_message()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use dbus::blocking::Connection;
+    use std::time::Duration;
+
+    use super::{Check, Status};
+    use crate::config::os_username;
+
+    const DBUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Whether a D-Bus name currently has an owner on `connection`
+    fn name_has_owner(connection: &Connection, name: &str) -> Result<bool, dbus::Error> {
+        let proxy = connection.with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", DBUS_TIMEOUT);
+        let (has_owner,): (bool,) =
+            proxy.method_call("org.freedesktop.DBus", "NameHasOwner", (name,))?;
+        Ok(has_owner)
+    }
+
+    pub fn check_bluez() -> Check {
+        let result = Connection::new_system().and_then(|bus| name_has_owner(&bus, "org.bluez"));
+        match result {
+            Ok(true) => Check::new("bluez", Status::Pass, "BlueZ is running on the system bus"),
+            Ok(false) => Check::new("bluez", Status::Fail, "BlueZ (org.bluez) is not running")
+                .hint("sudo systemctl enable --now bluetooth"),
+            Err(e) => Check::new("bluez", Status::Fail, format!("Cannot reach the system bus: {}", e))
+                .hint("Make sure D-Bus is running"),
+        }
+    }
+
+    pub fn check_tray_host() -> Check {
+        let result = Connection::new_session()
+            .and_then(|bus| name_has_owner(&bus, "org.kde.StatusNotifierWatcher"));
+        match result {
+            Ok(true) => Check::new("tray", Status::Pass, "A StatusNotifier tray host is running"),
+            Ok(false) => Check::new("tray", Status::Warn, "No StatusNotifierWatcher on the session bus")
+                .hint("The tray icon won't show. Enable the Waybar tray module or the GNOME AppIndicator extension"),
+            Err(e) => Check::new("tray", Status::Warn, format!("Cannot reach the session bus: {}", e))
+                .hint("Only needed for the tray icon; the command line works without it"),
+        }
+    }
+
+    pub fn check_bluetooth_group() -> Check {
+        let group_file = std::fs::read_to_string("/etc/group").unwrap_or_default();
+        let Some(gid) = group_id(&group_file, "bluetooth") else {
+            return Check::new("group", Status::Skip, "This system has no `bluetooth` group");
+        };
+
+        let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+        let in_session = process_groups(&status).contains(&gid);
+        let in_group_file =
+            os_username().is_some_and(|user| group_members(&group_file, "bluetooth").contains(&user.as_str()));
+        match (in_session, in_group_file) {
+            (true, _) => Check::new("group", Status::Pass, "User is in the `bluetooth` group"),
+            // usermod only changes /etc/group; running sessions keep their groups
+            (false, true) => Check::new(
+                "group",
+                Status::Warn,
+                "User was added to the `bluetooth` group, but this session started before that",
+            )
+            .hint("Log out and back in (or reboot) for the new group to apply"),
+            // Many distributions grant access through polkit instead
+            (false, false) => Check::new("group", Status::Warn, "User is not in the `bluetooth` group")
+                .hint("If scanning fails with a permission error: sudo usermod -aG bluetooth $USER, then log in again"),
+        }
+    }
+
+    /// Look up a group's id in /etc/group content
+    pub(super) fn group_id(group_file: &str, name: &str) -> Option<u32> {
+        group_file.lines().find_map(|line| {
+            let mut fields = line.split(':');
+            if fields.next()? != name {
+                return None;
+            }
+            fields.nth(1)?.parse().ok()
+        })
+    }
+
+    /// User names listed as members of a group in /etc/group content
+    pub(super) fn group_members<'a>(group_file: &'a str, name: &str) -> Vec<&'a str> {
+        group_file
+            .lines()
+            .find_map(|line| {
+                let mut fields = line.split(':');
+                if fields.next()? != name {
+                    return None;
+                }
+                fields.nth(2)
+            })
+            .map(|members| members.split(',').filter(|member| !member.is_empty()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Supplementary group ids from /proc/self/status content
+    pub(super) fn process_groups(status: &str) -> Vec<u32> {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("Groups:"))
+            .map(|groups| groups.split_whitespace().filter_map(|g| g.parse().ok()).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_group_parsing() {
+        let group_file = "root:x:0:\nwheel:x:10:alex\nbluetooth:x:963:alex\n";
+        assert_eq!(linux::group_id(group_file, "bluetooth"), Some(963));
+        assert_eq!(linux::group_id(group_file, "audio"), None);
+        assert_eq!(linux::group_members(group_file, "bluetooth"), ["alex"]);
+        assert!(linux::group_members(group_file, "root").is_empty());
+
+        let status = "Name:\tdesk-control\nGroups:\t10 963 1000 \nNgid:\t0\n";
+        assert_eq!(linux::process_groups(status), vec![10, 963, 1000]);
+        assert!(linux::process_groups("Name:\tx\n").is_empty());
+    }
+
+    #[test]
+    fn test_has_failures() {
+        let mut checks = vec![
+            Check::new("config", Status::Pass, "ok"),
+            Check::new("tray", Status::Warn, "no tray"),
+        ];
+        assert!(!has_failures(&checks));
+
+        checks.push(Check::new("desk", Status::Fail, "unreachable"));
+        assert!(has_failures(&checks));
+    }
+}
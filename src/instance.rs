@@ -0,0 +1,106 @@
+use anyhow::{Context, Result};
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+/// Held by the one process per user allowed to run as `name` (the tray, the daemon)
+///
+/// The lock is an flock on a file, so the system drops it when its holder
+/// exits, even after a crash. The file records the holder's pid.
+pub struct InstanceLock {
+    file: File,
+}
+
+impl InstanceLock {
+    /// Take the lock for this process, or `None` if another process holds it
+    pub fn try_acquire(name: &str) -> Result<Option<Self>> {
+        Self::try_acquire_at(&lock_path(name)?)
+    }
+
+    fn try_acquire_at(path: &Path) -> Result<Option<Self>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+
+        // Not truncated on open: until we hold the lock the pid is someone else's
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        match file.try_lock_exclusive() {
+            Ok(()) => {}
+            Err(e) if is_contended(&e) => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to lock {}", path.display())),
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        Ok(Some(Self { file }))
+    }
+
+    /// Pid of the process holding the lock `name`, as it recorded it
+    pub fn holder(name: &str) -> Option<u32> {
+        Self::holder_at(&lock_path(name).ok()?)
+    }
+
+    fn holder_at(path: &Path) -> Option<u32> {
+        let mut pid = String::new();
+        File::open(path).ok()?.read_to_string(&mut pid).ok()?;
+        pid.trim().parse().ok()
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // fs2's, not the std method of the same name (Rust 1.89+)
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// Whether locking failed because another process holds the lock
+fn is_contended(error: &std::io::Error) -> bool {
+    error.kind() == std::io::ErrorKind::WouldBlock
+        || error.raw_os_error() == fs2::lock_contended_error().raw_os_error()
+}
+
+/// Where the lock `name` lives: next to the daemon's socket
+fn lock_path(name: &str) -> Result<PathBuf> {
+    match dirs::runtime_dir() {
+        Some(dir) => Ok(dir.join(format!("desk-control-{}.lock", name))),
+        None => Ok(Config::config_dir()?.join(format!("{}.lock", name))),
+    }
+}
+
+/// " (pid 1234)" for messages about the holder of `name`, if known
+pub fn holder_suffix(name: &str) -> String {
+    InstanceLock::holder(name)
+        .map(|pid| format!(" (pid {})", pid))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_holder_at_a_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tray.lock");
+
+        let lock = InstanceLock::try_acquire_at(&path).unwrap().unwrap();
+        assert_eq!(InstanceLock::holder_at(&path), Some(std::process::id()));
+        // flock conflicts between open files, even within one process
+        assert!(InstanceLock::try_acquire_at(&path).unwrap().is_none());
+        assert_eq!(InstanceLock::holder_at(&path), Some(std::process::id()));
+
+        drop(lock);
+        assert!(InstanceLock::try_acquire_at(&path).unwrap().is_some());
+    }
+}
@@ -1,88 +1,154 @@
+mod autostart;
+mod backoff;
+mod bar;
+mod calibrate;
+mod cli;
 mod config;
+mod config_keys;
+mod daemon;
+#[cfg(target_os = "linux")]
+mod dbus_service;
 mod desk;
+mod doctor;
+mod instance;
+mod metrics;
+mod movement;
+mod range;
+#[cfg(target_os = "linux")]
+mod systemd;
 mod ui;
+mod units;
 
 use anyhow::Result;
-use btleplug::api::Peripheral;
-use config::{Config, DrinkSize};
-use desk::DeskController;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::Mutex;
+use calibrate::Prompt;
+use clap::Parser;
+use cli::Cli;
+use config::{Config, DeskRange, DrinkSize};
+use daemon::{DaemonClient, Desk};
+use desk::error::user_message_for;
+use desk::watch::WatchEvent;
+use desk::{Calibration, DeskController, DeskResult, Height, ScanReport};
+use metrics::MoveSource;
+use movement::MoveRequest;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, watch, Mutex};
 
 use ui::{TrayApp, MenuCallback};
 
 /// Application state shared between UI and background tasks
 struct AppState {
     config: Mutex<Config>,
-    desk_controller: Mutex<Option<DeskController>>,
+    /// The desk, reached through the daemon (which may run in this process)
+    desk: Desk,
+    /// Latest config, watched by the UI thread to refresh the menu
+    config_changed: watch::Sender<Config>,
+    /// Questions for the UI thread to ask with a dialog
+    prompts: mpsc::Sender<PromptRequest>,
 }
 
 impl AppState {
-    fn new(config: Config) -> Self {
+    fn new(config: Config, desk: Desk, prompts: mpsc::Sender<PromptRequest>) -> Self {
         Self {
+            config_changed: watch::Sender::new(config.clone()),
             config: Mutex::new(config),
-            desk_controller: Mutex::new(None),
+            desk,
+            prompts,
+        }
+    }
+
+    /// Replace the in-memory config with one re-read from disk, keeping the
+    /// profile selected for this session
+    async fn replace_config(&self, mut config: Config) {
+        {
+            let mut current = self.config.lock().await;
+            config.inherit_session_profile(&current);
+            *current = config.clone();
         }
+        self.config_changed.send_replace(config);
     }
 
-    /// Ensure we're connected to the desk
-    async fn ensure_connected(&self) -> Result<()> {
-        let mut controller = self.desk_controller.lock().await;
+    /// Make `name` the active profile and save it as the default
+    async fn switch_profile(&self, name: &str) -> Result<()> {
+        if !self.config.lock().await.profiles.contains_key(name) {
+            return Err(anyhow::anyhow!("Profile '{}' does not exist", name));
+        }
 
-        if controller.is_none() {
-            log::info!("No active desk connection, initiating new connection...");
-            let config = self.config.lock().await;
-            let desk_address = config.desk_address.clone();
-            drop(config); // Release lock before async operation
+        let active = name.to_string();
+        let updated =
+            tokio::task::spawn_blocking(move || Config::update(|config| config.active_profile = active)).await??;
+        *self.config.lock().await = updated.clone();
+        self.config_changed.send_replace(updated);
 
-            if desk_address.is_none() {
-                return Err(anyhow::anyhow!("No desk configured. Please configure a desk first."));
-            }
+        log::info!("Switched to profile '{}'", name);
+        Ok(())
+    }
+
+    /// Move desk as requested for `source`, returning the target height
+    async fn move_desk(&self, request: MoveRequest, source: MoveSource) -> DeskResult<Height> {
+        log::info!("=== Starting move to {} ===", request);
+
+        let current = match request.is_relative() {
+            true => self.desk.get_height().await?,
+            false => Height::default(),
+        };
+        let height = request.resolve(&*self.config.lock().await, current)?;
+        log::info!("Target height: {}mm", height.as_mm());
 
-            log::info!("Connecting to desk at address: {:?}", desk_address);
-            let desk = DeskController::connect(desk_address).await?;
-            *controller = Some(desk);
-            log::info!("Desk connection established and cached");
-        } else {
-            log::info!("Using existing desk connection (no scan needed)");
+        match &self.desk {
+            Desk::Daemon(client) => client.clone().with_source(source).move_to_height(height).await?,
+            desk => desk.move_to_height(height).await?,
         }
+        log::info!("=== Successfully moved to {} ===", request);
 
-        Ok(())
+        Ok(height)
     }
 
-    /// Move desk to a specific preset
-    async fn move_to_preset(&self, preset: DrinkSize) -> Result<()> {
-        log::info!("=== Starting move to {} preset ===", preset.name());
+    /// Read the live desk height and store it as `preset` in the active profile
+    async fn save_current_height_as(&self, preset: DrinkSize) -> Result<Height> {
+        let height = self.desk.get_height().await?;
 
-        self.ensure_connected().await?;
+        // Writing the file blocks, so it happens off the runtime and without the lock
+        let config = self.config.lock().await.clone();
+        let updated = tokio::task::spawn_blocking(move || config.save_preset(preset, height)).await??;
+        self.replace_config(updated).await;
 
-        let config = self.config.lock().await;
-        let height_mm = config.get_preset(preset);
-        drop(config);
+        log::info!("Saved {}mm as {} preset", height.as_mm(), preset.name());
+        Ok(height)
+    }
 
-        log::info!("Target height: {}mm ({:.1}cm)", height_mm, height_mm as f32 / 10.0);
+    /// Run the calibration wizard with dialogs and save the result
+    ///
+    /// Returns `None` if the user cancelled.
+    async fn calibrate(&self) -> Result<Option<Calibration>> {
+        let unit = self.config.lock().await.display_unit;
+        let calibration = calibrate::run(&self.desk, unit, &self.prompt()).await?;
 
-        let controller = self.desk_controller.lock().await;
-        if let Some(desk) = controller.as_ref() {
-            log::info!("Sending move command to desk...");
-            desk.move_to_height(height_mm).await?;
-            log::info!("=== Successfully moved to {} preset ===", preset.name());
-        } else {
-            log::error!("Controller was None after ensure_connected succeeded - this should not happen!");
-            return Err(anyhow::anyhow!("Desk controller unavailable"));
+        if let Some(calibration) = calibration {
+            self.replace_config(calibrate::save(Some(calibration))?).await;
+            log::info!("Saved calibration: {:?}", calibration);
         }
+        Ok(calibration)
+    }
 
-        Ok(())
+    /// Offer to drive the desk to its end stops, then save the range found
+    ///
+    /// Returns the range and the profiles whose presets were adjusted, or
+    /// `None` if the user declined.
+    async fn discover_range(&self) -> Result<Option<(DeskRange, Vec<String>)>> {
+        let Some(found) = range::discover(&self.desk, &self.prompt()).await? else {
+            return Ok(None);
+        };
+        let (updated, adjusted) = range::save(found)?;
+        self.replace_config(updated).await;
+        log::info!("Saved desk range: {} - {}", found.min, found.max);
+        Ok(Some((found, adjusted)))
     }
 
-    /// Get current desk height in millimeters (returns None if not connected)
-    async fn get_current_height(&self) -> Option<u16> {
-        let controller = self.desk_controller.lock().await;
-        if let Some(desk) = controller.as_ref() {
-            desk.get_height().await.ok()
-        } else {
-            None
+    /// Ask questions with dialogs on the UI thread
+    fn prompt(&self) -> DialogPrompt {
+        DialogPrompt {
+            requests: self.prompts.clone(),
         }
     }
 }
@@ -94,39 +160,111 @@ struct AppMenuCallback {
 }
 
 impl MenuCallback for AppMenuCallback {
-    fn on_preset_selected(&self, preset: DrinkSize) {
+    fn on_move(&self, request: MoveRequest) {
         let state = Arc::clone(&self.state);
         self.runtime.spawn(async move {
-            log::info!("Moving desk to {} preset", preset.name());
-            match state.move_to_preset(preset).await {
-                Ok(_) => {
-                    log::info!("Successfully moved to {} preset", preset.name());
-                    show_info_dialog(&format!("Desk moved to {} preset", preset.name()));
+            log::info!("Moving desk to {}", request);
+            match state.move_desk(request, MoveSource::Tray).await {
+                Ok(height) => {
+                    log::info!("Successfully moved to {}", request);
+                    let height = state.config.lock().await.format_height(height);
+                    let message = match request {
+                        MoveRequest::Preset(preset) => {
+                            format!("Desk moved to {} preset ({})", preset.name(), height)
+                        }
+                        _ => format!("Desk moved to {}", height),
+                    };
+                    show_info_dialog(&message);
                 }
                 Err(e) => {
-                    log::error!("Failed to move to preset {}: {}", preset.name(), e);
-                    show_error_dialog(&format!(
-                        "Failed to move desk: {}",
-                        e
-                    ));
+                    log::error!("Failed to move to {}: {}", request, e);
+                    show_error_dialog(&e.user_message());
                 }
             }
         });
     }
 
+    fn on_save_current_height(&self, preset: DrinkSize) {
+        let state = Arc::clone(&self.state);
+        self.runtime.spawn(async move {
+            match state.save_current_height_as(preset).await {
+                Ok(height) => {
+                    let height = state.config.lock().await.format_height(height);
+                    show_info_dialog(&format!("Saved {} as {} preset", height, preset.name()));
+                }
+                Err(e) => {
+                    log::error!("Failed to save {} preset: {}", preset.name(), e);
+                    show_error_dialog(&format!("Failed to save preset: {}", user_message_for(&e)));
+                }
+            }
+        });
+    }
+
+    fn on_profile_selected(&self, name: &str) {
+        let state = Arc::clone(&self.state);
+        let name = name.to_string();
+        self.runtime.spawn(async move {
+            if let Err(e) = state.switch_profile(&name).await {
+                log::error!("Failed to switch to profile {}: {}", name, e);
+                show_error_dialog(&format!("Failed to switch profile: {}", e));
+            }
+        });
+    }
+
     fn on_configure_desk(&self) {
         log::info!("Configure desk requested");
         let state = Arc::clone(&self.state);
         let runtime = Arc::clone(&self.runtime);
 
         runtime.spawn(async move {
-            match scan_and_configure_desk(state).await {
-                Ok(_) => {
-                    show_info_dialog("Desk configured successfully!");
+            match scan_and_configure_desk(Arc::clone(&state)).await {
+                Ok((desk, 0)) => {
+                    show_info_dialog(&format!("Desk configured successfully: {}", desk_label(&desk)));
+                }
+                Ok((desk, others)) => {
+                    show_info_dialog(&format!(
+                        "Desk configured successfully: {} (nearest of {} desks).\n\
+                        Run `desk-control scan` to list them and \
+                        `desk-control connect <address>` to pick another.",
+                        desk_label(&desk),
+                        others + 1
+                    ));
                 }
                 Err(e) => {
                     log::error!("Failed to configure desk: {}", e);
-                    show_error_dialog(&format!("Failed to configure desk: {}", e));
+                    show_error_dialog(&format!("Failed to configure desk: {}", user_message_for(&e)));
+                    return;
+                }
+            }
+
+            match state.discover_range().await {
+                Ok(Some((found, adjusted))) => {
+                    let config = state.config.lock().await.clone();
+                    show_info_dialog(&range::summary(&found, &config, &adjusted));
+                }
+                Ok(None) => log::info!("Range discovery skipped"),
+                Err(e) => {
+                    log::error!("Range discovery failed: {}", e);
+                    show_error_dialog(&format!("Could not find the desk's range: {}", user_message_for(&e)));
+                }
+            }
+        });
+    }
+
+    fn on_calibrate(&self) {
+        log::info!("Calibration requested");
+        let state = Arc::clone(&self.state);
+
+        self.runtime.spawn(async move {
+            match state.calibrate().await {
+                Ok(Some(calibration)) => {
+                    let config = state.config.lock().await.clone();
+                    show_info_dialog(&calibrate::summary(&calibration, &config));
+                }
+                Ok(None) => log::info!("Calibration cancelled"),
+                Err(e) => {
+                    log::error!("Calibration failed: {}", e);
+                    show_error_dialog(&format!("Calibration failed: {}", user_message_for(&e)));
                 }
             }
         });
@@ -135,11 +273,25 @@ impl MenuCallback for AppMenuCallback {
     fn on_configure_presets(&self) {
         log::info!("Configure presets requested");
         show_info_dialog(
-            "To configure presets, edit the config file at:\n~/.desk-control/config\n\n\
-            Heights are in millimeters (e.g., 1050 = 105cm)"
+            "To configure presets, edit the profiles in the config file at:\n\
+            ~/.desk-control/config\n\n\
+            Heights are in millimeters (e.g., 1050 = 105cm) or text with a unit \
+            (e.g., \"105cm\", \"41.5in\").\n\
+            Set \"display_unit\" to cm, mm or in to change how heights are shown."
         );
     }
 
+    fn on_start_at_login(&self, enabled: bool) -> Result<()> {
+        match enabled {
+            true => autostart::enable().map(|_| ()),
+            false => autostart::disable().map(|_| ()),
+        }
+    }
+
+    fn on_error(&self, message: &str) {
+        show_error_dialog(message);
+    }
+
     fn on_quit(&self) {
         log::info!("Quitting application");
         #[cfg(target_os = "linux")]
@@ -153,45 +305,179 @@ impl MenuCallback for AppMenuCallback {
     }
 }
 
-/// Scan for desks and let user select one
-async fn scan_and_configure_desk(state: Arc<AppState>) -> Result<()> {
+/// Scan for desks and configure the nearest one
+///
+/// Returns the chosen desk and how many other desks were seen, so the user
+/// can pick another with `desk-control connect <address>` if needed.
+async fn scan_and_configure_desk(state: Arc<AppState>) -> Result<(ScanReport, usize)> {
     log::info!("Scanning for desks...");
 
-    let desks = DeskController::scan_for_desks(10).await?;
+    let configured = state.config.lock().await.desk_address.clone();
+    let mut desks = DeskController::scan(10, configured.as_deref(), false).await?;
 
     if desks.is_empty() {
         return Err(anyhow::anyhow!("No desks found"));
     }
 
     log::info!("Found {} desk(s)", desks.len());
+    for desk in &desks {
+        log::info!("  {} {:?} rssi={:?}", desk.address, desk.name, desk.rssi);
+    }
 
-    // For now, just connect to the first one
-    // In a full implementation, you'd show a dialog to select
-    let desk = desks.into_iter().next().unwrap();
-
-    let address = if let Ok(Some(props)) = desk.properties().await {
-        props.address.to_string()
-    } else {
-        return Err(anyhow::anyhow!("Could not get desk properties"));
-    };
+    // Reports are sorted nearest first, so the strongest signal wins
+    let others = desks.len() - 1;
+    let desk = desks.remove(0);
+    let address = desk.address.clone();
 
-    // Update config with desk address
-    let mut config = state.config.lock().await;
-    config.desk_address = Some(address.clone());
-    config.save()?;
-    drop(config);
+    // Update config with desk address (re-reads the file so concurrent edits are kept).
+    // The daemon sees the change and connects to the new desk.
+    let updated = Config::update(|config| config.set_desk_address(&address))?;
+    state.replace_config(updated).await;
 
     log::info!("Configured desk: {}", address);
 
-    // Connect to the desk and keep the connection alive for future use
-    log::info!("Establishing connection to configured desk...");
-    let new_controller = DeskController::connect(Some(address)).await?;
-    let mut controller = state.desk_controller.lock().await;
-    *controller = Some(new_controller);
+    Ok((desk, others))
+}
+
+/// Name and address of a scanned desk, for messages
+fn desk_label(desk: &ScanReport) -> String {
+    match &desk.name {
+        Some(name) => format!("{} ({})", name, desk.address),
+        None => desk.address.clone(),
+    }
+}
+
+/// Reload the config when another process (e.g. `desk-control config set`)
+/// changes the file, so the menu and presets stay current
+async fn watch_config_file(state: Arc<AppState>) {
+    let (changes, mut changed) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(Config::watch(changes));
+
+    while let Some(config) = changed.recv().await {
+        state.replace_config(config).await;
+    }
+}
+
+/// Pass the daemon's desk events to the UI thread for the menu and D-Bus
+///
+/// Subscribes again if the daemon restarts.
+async fn follow_desk(client: DaemonClient, updates: mpsc::Sender<WatchEvent>) {
+    loop {
+        let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let forward = async {
+            while let Some(event) = received.recv().await {
+                let _ = updates.send(event);
+            }
+        };
+
+        let (subscription, ()) = tokio::join!(client.subscribe(events), forward);
+        if let Err(e) = subscription {
+            log::warn!("Lost the daemon's desk events: {}", e);
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+/// Carry out a D-Bus method call on the runtime, answering once the desk is done
+#[cfg(target_os = "linux")]
+fn run_dbus_call(
+    state: &Arc<AppState>,
+    runtime: &tokio::runtime::Runtime,
+    call: dbus_service::Call,
+    reply: dbus_service::Reply,
+) {
+    use dbus_service::Call;
+
+    log::info!("D-Bus call: {}", call);
+    let state = Arc::clone(state);
+    runtime.spawn(async move {
+        let result = match call {
+            Call::MoveToPreset(preset) => state.move_desk(MoveRequest::Preset(preset), MoveSource::Dbus).await.map(Some),
+            Call::MoveToHeight(height) => state.move_desk(MoveRequest::Absolute(height), MoveSource::Dbus).await.map(Some),
+            Call::Stop => state.desk.stop().await.map(|()| None),
+        };
+        match result {
+            Ok(height) => reply.send(height),
+            Err(e) => {
+                log::error!("D-Bus call {} failed: {}", call, e);
+                reply.fail(&e);
+            }
+        }
+    });
+}
+
+/// Periodically remind the user to change position, per the active profile
+async fn run_reminders(state: Arc<AppState>) {
+    let mut last_reminder = Instant::now();
 
-    log::info!("Desk connected and ready to use");
+    loop {
+        tokio::time::sleep(Duration::from_secs(60)).await;
 
-    Ok(())
+        let reminders = state.config.lock().await.profile().reminders.clone();
+        if !reminders.enabled {
+            last_reminder = Instant::now();
+            continue;
+        }
+
+        let interval = Duration::from_secs(u64::from(reminders.interval_minutes.max(1)) * 60);
+        if last_reminder.elapsed() >= interval {
+            last_reminder = Instant::now();
+            log::info!("Showing position reminder");
+            show_info_dialog("Time to change position! Pick a preset to sit or stand.");
+        }
+    }
+}
+
+/// A question for the user, answered on the UI thread with a modal dialog
+struct PromptRequest {
+    question: String,
+    /// Whether a typed answer is expected rather than yes/no
+    wants_text: bool,
+    answer: oneshot::Sender<Option<String>>,
+}
+
+/// Asks the calibration wizard's questions through dialogs on the UI thread
+struct DialogPrompt {
+    requests: mpsc::Sender<PromptRequest>,
+}
+
+impl DialogPrompt {
+    async fn request(&self, question: &str, wants_text: bool) -> Option<String> {
+        let (answer, answered) = oneshot::channel();
+        self.requests
+            .send(PromptRequest {
+                question: question.to_string(),
+                wants_text,
+                answer,
+            })
+            .ok()?;
+        answered.await.ok().flatten()
+    }
+}
+
+impl Prompt for DialogPrompt {
+    fn say(&self, message: &str) {
+        show_info_dialog(message);
+    }
+
+    async fn confirm(&self, question: &str) -> bool {
+        self.request(question, false).await.is_some()
+    }
+
+    async fn ask(&self, question: &str) -> Option<String> {
+        self.request(question, true).await.filter(|answer| !answer.trim().is_empty())
+    }
+}
+
+/// Show the dialogs for queued questions (call this on the UI thread)
+fn answer_prompts(requests: &mpsc::Receiver<PromptRequest>) {
+    while let Ok(request) = requests.try_recv() {
+        let answer = match request.wants_text {
+            true => show_input_dialog(&request.question),
+            false => show_question_dialog(&request.question).then(String::new),
+        };
+        let _ = request.answer.send(answer);
+    }
 }
 
 /// Show an error dialog (macOS)
@@ -230,6 +516,62 @@ fn show_info_dialog(message: &str) {
     }
 }
 
+/// Ask a yes/no question (macOS)
+#[cfg(target_os = "macos")]
+fn show_question_dialog(question: &str) -> bool {
+    use cocoa::appkit::NSAlert;
+    use cocoa::base::nil;
+    use cocoa::foundation::NSString;
+
+    unsafe {
+        let alert = NSAlert::alloc(nil);
+        let title = NSString::alloc(nil).init_str("Desk Control");
+        let msg = NSString::alloc(nil).init_str(question);
+
+        let _: () = objc::msg_send![alert, setMessageText: title];
+        let _: () = objc::msg_send![alert, setInformativeText: msg];
+        let _: cocoa::base::id = objc::msg_send![alert, addButtonWithTitle: NSString::alloc(nil).init_str("Yes")];
+        let _: cocoa::base::id = objc::msg_send![alert, addButtonWithTitle: NSString::alloc(nil).init_str("No")];
+
+        // NSAlertFirstButtonReturn
+        let response: cocoa::foundation::NSInteger = objc::msg_send![alert, runModal];
+        response == 1000
+    }
+}
+
+/// Ask for a line of text, `None` if cancelled (macOS)
+#[cfg(target_os = "macos")]
+fn show_input_dialog(question: &str) -> Option<String> {
+    use cocoa::appkit::NSAlert;
+    use cocoa::base::{id, nil};
+    use cocoa::foundation::{NSInteger, NSPoint, NSRect, NSSize, NSString};
+
+    unsafe {
+        let alert = NSAlert::alloc(nil);
+        let title = NSString::alloc(nil).init_str("Desk Control");
+        let msg = NSString::alloc(nil).init_str(question);
+
+        let _: () = objc::msg_send![alert, setMessageText: title];
+        let _: () = objc::msg_send![alert, setInformativeText: msg];
+        let _: id = objc::msg_send![alert, addButtonWithTitle: NSString::alloc(nil).init_str("OK")];
+        let _: id = objc::msg_send![alert, addButtonWithTitle: NSString::alloc(nil).init_str("Cancel")];
+
+        let field: id = objc::msg_send![objc::class!(NSTextField), alloc];
+        let frame = NSRect::new(NSPoint::new(0.0, 0.0), NSSize::new(200.0, 24.0));
+        let field: id = objc::msg_send![field, initWithFrame: frame];
+        let _: () = objc::msg_send![alert, setAccessoryView: field];
+
+        let response: NSInteger = objc::msg_send![alert, runModal];
+        if response != 1000 {
+            return None;
+        }
+
+        let value: id = objc::msg_send![field, stringValue];
+        let text = std::ffi::CStr::from_ptr(value.UTF8String());
+        Some(text.to_string_lossy().into_owned())
+    }
+}
+
 /// Show an error notification (Linux)
 #[cfg(target_os = "linux")]
 fn show_error_dialog(message: &str) {
@@ -264,6 +606,53 @@ fn show_info_dialog(message: &str) {
     }
 }
 
+/// Ask a yes/no question in a modal dialog (Linux)
+#[cfg(target_os = "linux")]
+fn show_question_dialog(question: &str) -> bool {
+    use gtk::prelude::*;
+
+    let dialog = gtk::MessageDialog::new(
+        None::<&gtk::Window>,
+        gtk::DialogFlags::MODAL,
+        gtk::MessageType::Question,
+        gtk::ButtonsType::YesNo,
+        question,
+    );
+    dialog.set_title("Desk Control");
+    let response = dialog.run();
+    dialog.close();
+    response == gtk::ResponseType::Yes
+}
+
+/// Ask for a line of text in a modal dialog, `None` if cancelled (Linux)
+#[cfg(target_os = "linux")]
+fn show_input_dialog(question: &str) -> Option<String> {
+    use gtk::prelude::*;
+
+    let dialog = gtk::Dialog::with_buttons(
+        Some("Desk Control"),
+        None::<&gtk::Window>,
+        gtk::DialogFlags::MODAL,
+        &[("Cancel", gtk::ResponseType::Cancel), ("OK", gtk::ResponseType::Ok)],
+    );
+    dialog.set_default_response(gtk::ResponseType::Ok);
+
+    let entry = gtk::Entry::new();
+    entry.set_activates_default(true);
+
+    let content = dialog.content_area();
+    content.set_spacing(8);
+    content.set_border_width(12);
+    content.add(&gtk::Label::new(Some(question)));
+    content.add(&entry);
+
+    dialog.show_all();
+    let response = dialog.run();
+    let text = entry.text().to_string();
+    dialog.close();
+    (response == gtk::ResponseType::Ok).then_some(text)
+}
+
 #[cfg(not(any(target_os = "macos", target_os = "linux")))]
 fn show_error_dialog(message: &str) {
     eprintln!("Error: {}", message);
@@ -274,14 +663,65 @@ fn show_info_dialog(message: &str) {
     println!("Info: {}", message);
 }
 
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn show_question_dialog(_question: &str) -> bool {
+    false
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+fn show_input_dialog(_question: &str) -> Option<String> {
+    None
+}
+
+/// `tui.log` next to the config, started afresh by every `desk-control tui`
+fn tui_log_file() -> Option<std::fs::File> {
+    let dir = Config::config_dir().ok()?;
+    std::fs::create_dir_all(&dir).ok()?;
+    std::fs::File::create(dir.join("tui.log")).ok()
+}
+
 fn main() -> Result<()> {
-    // Initialize logging
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
+    let cli = Cli::parse();
+
+    // Initialize logging (subcommands keep stderr quiet unless something goes wrong;
+    // the daemon runs unattended, so it logs like the tray)
+    let mut logger = env_logger::Builder::from_default_env();
+    match cli.command {
+        Some(cli::Command::Daemon) | None => logger.filter_level(log::LevelFilter::Info),
+        // Anything on stderr would draw over the full-screen UI, including the
+        // warnings of a daemon it hosts
+        Some(cli::Command::Tui) => match tui_log_file() {
+            Some(file) => logger
+                .filter_level(log::LevelFilter::Info)
+                .target(env_logger::Target::Pipe(Box::new(file))),
+            None => logger.filter_level(log::LevelFilter::Off),
+        },
+        Some(_) => logger.filter_level(log::LevelFilter::Warn),
+    };
+    logger.init();
+
+    // Subcommands run headless and never touch GTK or the tray,
+    // and report failures through documented exit codes
+    if let Some(command) = cli.command {
+        if let Err(e) = cli::run(command, &cli.global) {
+            eprintln!("Error: {:#}", e);
+            std::process::exit(desk::error::exit_code_for(&e));
+        }
+        return Ok(());
+    }
 
     log::info!("Starting Desk Control application");
 
+    // One tray per user; commands reach the running one through its daemon
+    let Some(_instance) = instance::InstanceLock::try_acquire("tray")? else {
+        eprintln!(
+            "Desk Control is already running in the tray{}. Use commands like `desk-control move grande` to control it.",
+            instance::holder_suffix("tray")
+        );
+        // Not a failure: a unit with Restart=on-failure shouldn't keep trying
+        return Ok(());
+    };
+
     // Initialize GTK on Linux (required by tray-icon)
     #[cfg(target_os = "linux")]
     {
@@ -292,8 +732,9 @@ fn main() -> Result<()> {
     }
 
     // Load configuration
-    let config = Config::load()?;
-    log::info!("Configuration loaded");
+    let mut config = Config::load()?;
+    config.select_session_profile(cli.global.profile.as_deref())?;
+    log::info!("Configuration loaded (profile: {})", config.active_profile_name());
 
     // Create async runtime for background tasks
     let runtime = Arc::new(
@@ -301,8 +742,18 @@ fn main() -> Result<()> {
             .expect("Failed to create Tokio runtime"),
     );
 
+    // The daemon owns the desk connection; host it here unless one is running
+    let client = runtime.block_on(daemon::connect_or_start(config.clone()))?;
+
     // Create application state
-    let state = Arc::new(AppState::new(config.clone()));
+    let (prompt_tx, prompt_rx) = mpsc::channel();
+    let (event_tx, event_rx) = mpsc::channel();
+    let state = Arc::new(AppState::new(config.clone(), Desk::Daemon(client.clone()), prompt_tx));
+    let mut config_rx = state.config_changed.subscribe();
+
+    runtime.spawn(run_reminders(Arc::clone(&state)));
+    runtime.spawn(watch_config_file(Arc::clone(&state)));
+    runtime.spawn(follow_desk(client, event_tx));
 
     // Clone state and runtime for callback (they will be moved)
     let state_for_callback = Arc::clone(&state);
@@ -315,6 +766,8 @@ fn main() -> Result<()> {
     });
 
     // Create tray app
+    #[cfg(target_os = "linux")]
+    let properties = dbus_service::Properties::new(&config);
     let tray_app = TrayApp::new(config, callback)?;
 
     log::info!("System tray app started");
@@ -329,37 +782,48 @@ fn main() -> Result<()> {
         let tray_app_rc = Rc::new(RefCell::new(tray_app));
         let tray_app_clone = Rc::clone(&tray_app_rc);
 
+        // Let desktop shortcuts and scripts drive the desk over the session bus
+        let mut dbus = match dbus_service::DbusService::start(properties) {
+            Ok(service) => Some(service),
+            Err(e) => {
+                log::warn!("D-Bus service unavailable: {:#}", e);
+                None
+            }
+        };
+
         // Process tray events periodically using GTK's timeout mechanism
         glib::timeout_add_local(Duration::from_millis(100), move || {
+            // The daemon hosted here stopped after Ctrl-C or SIGTERM
+            if daemon::hosted_daemon_stopped() {
+                gtk::main_quit();
+                return glib::ControlFlow::Break;
+            }
             tray_app_clone.borrow().process_events();
-            glib::ControlFlow::Continue
-        });
-
-        // Update current height display periodically
-        let tray_app_height = Rc::clone(&tray_app_rc);
-        let state_height = Arc::clone(&state);
-        let runtime_height = Arc::clone(&runtime);
-
-        glib::timeout_add_local(Duration::from_secs(5), move || {
-            let state = Arc::clone(&state_height);
-            let tray_app = Rc::clone(&tray_app_height);
-
-            // Spawn async task to get height (don't capture Rc in the async block)
-            let (tx, rx) = std::sync::mpsc::channel();
-            runtime_height.spawn(async move {
-                if let Some(height_mm) = state.get_current_height().await {
-                    let _ = tx.send(height_mm);
+            answer_prompts(&prompt_rx);
+            let mut properties = dbus.as_ref().map(|service| service.properties().clone());
+            if config_rx.has_changed().unwrap_or(false) {
+                let config = config_rx.borrow_and_update();
+                tray_app_clone.borrow().refresh(&config);
+                if let Some(properties) = properties.as_mut() {
+                    properties.set_config(&config);
                 }
-            });
-
-            // Schedule UI update on main thread using received height
-            glib::timeout_add_local_once(Duration::from_millis(100), move || {
-                if let Ok(height_mm) = rx.try_recv() {
-                    let height_cm = height_mm as f32 / 10.0;
-                    tray_app.borrow().update_current_height(height_cm);
+            }
+            let mut height = None;
+            for event in event_rx.try_iter() {
+                if let WatchEvent::Height { height_mm, .. } = event {
+                    height = Some(height_mm);
                 }
-            });
-
+                if let Some(properties) = properties.as_mut() {
+                    properties.apply(&event);
+                }
+            }
+            if let Some(height) = height {
+                tray_app_clone.borrow().update_current_height(height);
+            }
+            if let (Some(service), Some(properties)) = (dbus.as_mut(), properties) {
+                service.update(properties);
+                service.process(|call, reply| run_dbus_call(&state, &runtime, call, reply));
+            }
             glib::ControlFlow::Continue
         });
 
@@ -370,28 +834,20 @@ fn main() -> Result<()> {
 
     #[cfg(not(target_os = "linux"))]
     {
-        use std::time::Instant;
-
         // On other platforms, use simple polling loop
-        let mut last_height_update = Instant::now();
-
-        loop {
+        while !daemon::hosted_daemon_stopped() {
             tray_app.process_events();
-
-            // Update height display every 5 seconds
-            if last_height_update.elapsed() >= Duration::from_secs(5) {
-                last_height_update = Instant::now();
-
-                let state_clone = Arc::clone(&state);
-                let height_future = async move {
-                    state_clone.get_current_height().await
-                };
-
-                if let Some(height_mm) = runtime.block_on(height_future) {
-                    let height_cm = height_mm as f32 / 10.0;
-                    tray_app.update_current_height(height_cm);
-                    log::debug!("Updated current height: {:.1}cm", height_cm);
-                }
+            answer_prompts(&prompt_rx);
+            if config_rx.has_changed().unwrap_or(false) {
+                tray_app.refresh(&config_rx.borrow_and_update());
+            }
+            let height = event_rx.try_iter().filter_map(|event| match event {
+                WatchEvent::Height { height_mm, .. } => Some(height_mm),
+                _ => None,
+            }).last();
+            if let Some(height) = height {
+                tray_app.update_current_height(height);
+                log::debug!("Updated current height: {}mm", height.as_mm());
             }
 
             std::thread::sleep(Duration::from_millis(100));
@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::desk::{DeskError, Height};
+
+/// Upper bounds of the move duration histogram buckets, in seconds
+const DURATION_BUCKETS: [f64; 8] = [1.0, 2.0, 5.0, 10.0, 15.0, 20.0, 30.0, 60.0];
+
+/// Everything this process counted, served by the daemon on `/metrics`
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry::new());
+
+/// Who asked for a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MoveSource {
+    Cli,
+    Tray,
+    Dbus,
+    Tui,
+    Bar,
+    Http,
+    Mqtt,
+    /// A script talking to the socket itself
+    #[serde(other)]
+    Socket,
+}
+
+impl MoveSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Cli => "cli",
+            Self::Tray => "tray",
+            Self::Dbus => "dbus",
+            Self::Tui => "tui",
+            Self::Bar => "bar",
+            Self::Http => "http",
+            Self::Mqtt => "mqtt",
+            Self::Socket => "socket",
+        }
+    }
+}
+
+/// Bluetooth operation on the desk that can fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BleOperation {
+    Connect,
+    Read,
+    Write,
+    Subscribe,
+}
+
+impl BleOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Connect => "connect",
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Subscribe => "subscribe",
+        }
+    }
+}
+
+struct Registry {
+    height: Option<Height>,
+    connected: bool,
+    moves: BTreeMap<(MoveSource, &'static str), u64>,
+    /// Cumulative counts per bucket of [`DURATION_BUCKETS`]
+    duration_buckets: [u64; DURATION_BUCKETS.len()],
+    duration_sum: f64,
+    duration_count: u64,
+    ble_errors: BTreeMap<BleOperation, u64>,
+    reconnects: u64,
+}
+
+impl Registry {
+    const fn new() -> Self {
+        Self {
+            height: None,
+            connected: false,
+            moves: BTreeMap::new(),
+            duration_buckets: [0; DURATION_BUCKETS.len()],
+            duration_sum: 0.0,
+            duration_count: 0,
+            ble_errors: BTreeMap::new(),
+            reconnects: 0,
+        }
+    }
+
+    /// A height read before the link dropped is stale, so it isn't exported
+    fn set_connected(&mut self, connected: bool) {
+        self.connected = connected;
+        if !connected {
+            self.height = None;
+        }
+    }
+
+    fn record_move(&mut self, source: MoveSource, outcome: &'static str, duration: Option<Duration>) {
+        *self.moves.entry((source, outcome)).or_default() += 1;
+
+        let Some(duration) = duration else {
+            return;
+        };
+        let secs = duration.as_secs_f64();
+        for (count, bound) in self.duration_buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if secs <= bound {
+                *count += 1;
+            }
+        }
+        self.duration_sum += secs;
+        self.duration_count += 1;
+    }
+
+    /// Prometheus text exposition format, version 0.0.4
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let header = |out: &mut String, name: &str, kind: &str, help: &str| {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+        };
+
+        header(&mut out, "desk_height_mm", "gauge", "Last reported desk height in millimeters, absent while disconnected");
+        if let Some(height) = self.height {
+            let _ = writeln!(out, "desk_height_mm {}", height.as_mm());
+        }
+
+        header(&mut out, "desk_connected", "gauge", "Whether the Bluetooth link to the desk is up");
+        let _ = writeln!(out, "desk_connected {}", u8::from(self.connected));
+
+        header(&mut out, "desk_moves_total", "counter", "Moves by who asked for them and how they ended");
+        for ((source, outcome), count) in &self.moves {
+            let _ = writeln!(
+                out,
+                "desk_moves_total{{source=\"{}\",outcome=\"{}\"}} {}",
+                source.name(),
+                outcome,
+                count
+            );
+        }
+
+        header(&mut out, "desk_move_duration_seconds", "histogram", "Time from starting a move until it ended");
+        for (bound, count) in DURATION_BUCKETS.iter().zip(self.duration_buckets) {
+            let _ = writeln!(out, "desk_move_duration_seconds_bucket{{le=\"{}\"}} {}", bound, count);
+        }
+        let _ = writeln!(out, "desk_move_duration_seconds_bucket{{le=\"+Inf\"}} {}", self.duration_count);
+        let _ = writeln!(out, "desk_move_duration_seconds_sum {}", self.duration_sum);
+        let _ = writeln!(out, "desk_move_duration_seconds_count {}", self.duration_count);
+
+        header(&mut out, "desk_ble_errors_total", "counter", "Failed Bluetooth operations on the desk");
+        for operation in [BleOperation::Connect, BleOperation::Read, BleOperation::Write, BleOperation::Subscribe] {
+            let count = self.ble_errors.get(&operation).copied().unwrap_or(0);
+            let _ = writeln!(out, "desk_ble_errors_total{{operation=\"{}\"}} {}", operation.name(), count);
+        }
+
+        header(&mut out, "desk_reconnects_total", "counter", "Connections to the desk after the first one");
+        let _ = writeln!(out, "desk_reconnects_total {}", self.reconnects);
+
+        out
+    }
+}
+
+/// How a move ended, as the `outcome` label
+fn outcome(error: Option<&DeskError>) -> &'static str {
+    match error {
+        None => "completed",
+        Some(DeskError::Interrupted) => "interrupted",
+        Some(DeskError::Obstructed { .. }) => "obstructed",
+        Some(DeskError::Timeout { .. }) => "timeout",
+        Some(DeskError::BelowMinimum { .. } | DeskError::AboveMaximum { .. }) => "out_of_range",
+        Some(DeskError::NotConfigured) => "not_configured",
+        Some(_) => "failed",
+    }
+}
+
+pub fn set_height(height: Height) {
+    REGISTRY.lock().unwrap().height = Some(height);
+}
+
+pub fn set_connected(connected: bool) {
+    REGISTRY.lock().unwrap().set_connected(connected);
+}
+
+/// Count a move asked for by `source` that ended with `error`, if any
+///
+/// `duration` is `None` for moves refused before the desk started.
+pub fn record_move(source: MoveSource, error: Option<&DeskError>, duration: Option<Duration>) {
+    REGISTRY.lock().unwrap().record_move(source, outcome(error), duration);
+}
+
+pub fn ble_error(operation: BleOperation) {
+    *REGISTRY.lock().unwrap().ble_errors.entry(operation).or_default() += 1;
+}
+
+pub fn reconnected() {
+    REGISTRY.lock().unwrap().reconnects += 1;
+}
+
+/// All metrics in the Prometheus text format
+pub fn render() -> String {
+    REGISTRY.lock().unwrap().render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_prometheus_text() {
+        let mut registry = Registry::new();
+        registry.height = Some(Height::from_units(10500));
+        registry.record_move(MoveSource::Tray, "completed", Some(Duration::from_secs(4)));
+        registry.record_move(MoveSource::Tray, "completed", Some(Duration::from_millis(1500)));
+        registry.record_move(MoveSource::Http, "not_configured", None);
+
+        let text = registry.render();
+        assert!(text.contains("# TYPE desk_moves_total counter\n"));
+        assert!(text.contains("desk_height_mm 1050\n"));
+        assert!(text.contains("desk_connected 0\n"));
+        assert!(text.contains("desk_moves_total{source=\"tray\",outcome=\"completed\"} 2\n"));
+        assert!(text.contains("desk_moves_total{source=\"http\",outcome=\"not_configured\"} 1\n"));
+        assert!(text.contains("desk_move_duration_seconds_bucket{le=\"1\"} 0\n"));
+        assert!(text.contains("desk_move_duration_seconds_bucket{le=\"2\"} 1\n"));
+        assert!(text.contains("desk_move_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+        assert!(text.contains("desk_move_duration_seconds_sum 5.5\n"));
+        assert!(text.contains("desk_ble_errors_total{operation=\"read\"} 0\n"));
+    }
+
+    #[test]
+    fn test_height_is_dropped_on_disconnect() {
+        let mut registry = Registry::new();
+        registry.set_connected(true);
+        registry.height = Some(Height::from_units(10500));
+        assert!(registry.render().contains("desk_height_mm 1050\n"));
+
+        registry.set_connected(false);
+        let text = registry.render();
+        assert!(!text.contains("\ndesk_height_mm "), "{}", text);
+        assert!(text.contains("desk_connected 0\n"));
+    }
+
+    #[test]
+    fn test_unknown_sources_count_as_socket() {
+        let source: MoveSource = serde_json::from_str("\"stream-deck\"").unwrap();
+        assert_eq!(source, MoveSource::Socket);
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+use crate::config::{Config, DrinkSize};
+use crate::desk::{DeskError, DeskResult, Height};
+use crate::units::{parse_height, DisplayUnit};
+
+/// Nudge of the tray's and terminal UI's small moves: 1cm, or 1/2in for inch users
+pub fn nudge_step(unit: DisplayUnit) -> Height {
+    match unit {
+        DisplayUnit::In => Height::from_units(127),
+        DisplayUnit::Cm | DisplayUnit::Mm => Height::from_units(100),
+    }
+}
+
+/// Where the user asked the desk to go, before it is resolved to a height
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveRequest {
+    /// An exact height ("105cm", "41.5in")
+    Absolute(Height),
+    /// A change from the current height, in 0.1mm desk units ("+2cm", "-0.5in")
+    Relative(i32),
+    /// A preset of the active profile ("grande")
+    Preset(DrinkSize),
+}
+
+impl MoveRequest {
+    /// Parse a preset name, an absolute height or a signed relative height
+    ///
+    /// Numbers without a unit use `default_unit`.
+    pub fn parse(input: &str, default_unit: DisplayUnit) -> Result<Self> {
+        let input = input.trim();
+
+        if let Some(preset) = DrinkSize::from_name(input) {
+            return Ok(Self::Preset(preset));
+        }
+
+        let (sign, amount) = match input.chars().next() {
+            Some('+') => (1, &input[1..]),
+            Some('-') => (-1, &input[1..]),
+            _ => return parse_height(input, default_unit).map(Self::Absolute),
+        };
+
+        let delta = parse_height(amount, default_unit)
+            .map_err(|_| anyhow!("Invalid relative height '{}'", input))?;
+        Ok(Self::Relative(sign * i32::from(delta.units())))
+    }
+
+    /// Move up by the nudge step for `unit`
+    pub fn nudge_up(unit: DisplayUnit) -> Self {
+        Self::Relative(i32::from(nudge_step(unit).units()))
+    }
+
+    /// Move down by the nudge step for `unit`
+    pub fn nudge_down(unit: DisplayUnit) -> Self {
+        Self::Relative(-i32::from(nudge_step(unit).units()))
+    }
+
+    /// Whether resolving this request needs the desk's current height
+    pub fn is_relative(&self) -> bool {
+        matches!(self, Self::Relative(_))
+    }
+
+    /// Turn the request into a target height for the active profile
+    ///
+    /// Absolute heights and presets beyond the profile's limits or the desk's discovered range are
+    /// rejected; relative moves stop at the limit instead, and fail only if the desk is already there.
+    pub fn resolve(&self, config: &Config, current: Height) -> DeskResult<Height> {
+        let limits = config.move_limits();
+
+        let delta = match *self {
+            Self::Absolute(height) => return limits.check(height).map(|_| height),
+            Self::Preset(preset) => {
+                let height = config.get_preset(preset);
+                return limits.check(height).map(|_| height);
+            }
+            Self::Relative(delta) => delta,
+        };
+
+        let target = i32::from(current.units()) + delta;
+        let requested = u16::try_from(target)
+            .ok()
+            .filter(|units| *units > 0)
+            .map(Height::from_units)
+            .ok_or_else(|| {
+                DeskError::InvalidTarget(format!("Moving {} from {} is out of range", self, current))
+            })?;
+        let target = limits.clamp(requested);
+
+        // Clamping may leave the desk where it is, or even send it the other way
+        match (delta > 0, limits.min, limits.max) {
+            (true, _, Some(max)) if target <= current => {
+                Err(DeskError::AboveMaximum { height: requested, max })
+            }
+            (false, Some(min), _) if target >= current => {
+                Err(DeskError::BelowMinimum { height: requested, min })
+            }
+            _ => Ok(target),
+        }
+    }
+}
+
+impl fmt::Display for MoveRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Absolute(height) => write!(f, "{}", height),
+            Self::Relative(delta) => {
+                let sign = if *delta < 0 { '-' } else { '+' };
+                let amount = Height::from_units(delta.unsigned_abs().min(u16::MAX as u32) as u16);
+                write!(f, "{}{}", sign, amount)
+            }
+            Self::Preset(preset) => write!(f, "{} preset", preset.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HeightLimits;
+
+    fn mm(mm: u16) -> Height {
+        Height::from_mm(mm).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let parse = |input| MoveRequest::parse(input, DisplayUnit::Cm).unwrap();
+
+        assert_eq!(parse("grande"), MoveRequest::Preset(DrinkSize::Grande));
+        assert_eq!(parse("105cm"), MoveRequest::Absolute(mm(1050)));
+        assert_eq!(parse("41.5in"), MoveRequest::Absolute(Height::from_units(10541)));
+        assert_eq!(parse("+2cm"), MoveRequest::Relative(200));
+        assert_eq!(parse("-0.5in"), MoveRequest::Relative(-127));
+        assert_eq!(parse("+3"), MoveRequest::Relative(300));
+
+        assert!(MoveRequest::parse("+", DisplayUnit::Cm).is_err());
+        assert!(MoveRequest::parse("-tall", DisplayUnit::Cm).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let config = Config::default();
+        let current = mm(1000);
+
+        assert_eq!(MoveRequest::Relative(200).resolve(&config, current).unwrap(), mm(1020));
+        assert_eq!(MoveRequest::Relative(-200).resolve(&config, current).unwrap(), mm(980));
+        assert_eq!(
+            MoveRequest::Preset(DrinkSize::Short).resolve(&config, current).unwrap(),
+            config.get_preset(DrinkSize::Short)
+        );
+        assert!(MoveRequest::Relative(-20000).resolve(&config, current).is_err());
+
+        let nudge = |unit| MoveRequest::nudge_up(unit).resolve(&config, current).unwrap();
+        assert_eq!(nudge(DisplayUnit::Cm), mm(1010));
+        assert_eq!(DisplayUnit::In.format(nudge(DisplayUnit::In).abs_diff(current)), "1/2in");
+    }
+
+    #[test]
+    fn test_resolve_respects_limits() {
+        let mut config = Config::default();
+        config.profile_mut().limits = HeightLimits {
+            min: Some(mm(700)),
+            max: Some(mm(1200)),
+        };
+
+        // Relative moves stop at the limit
+        assert_eq!(MoveRequest::Relative(500).resolve(&config, mm(1180)).unwrap(), mm(1200));
+        assert!(MoveRequest::Relative(100).resolve(&config, mm(1200)).is_err());
+        assert!(MoveRequest::Relative(-100).resolve(&config, mm(700)).is_err());
+
+        // Absolute targets outside the limits are rejected
+        assert!(MoveRequest::Absolute(mm(1250)).resolve(&config, mm(1000)).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Result};
+
+use crate::calibrate::Prompt;
+use crate::config::{Config, DeskRange};
+use crate::daemon::Desk;
+use crate::desk::{DeskError, Height, MovementCommand};
+
+/// Less travel than this between the end stops means the desk didn't really move (10cm)
+const MIN_TRAVEL: Height = Height::from_units(1000);
+
+/// Find the lowest and highest height the desk can reach, with the user's consent
+///
+/// Drives the desk to both end stops and back to where it started, also
+/// when a step fails. Returns `None` if the user declined; nothing is saved
+/// here, see [`save`].
+pub async fn discover<P: Prompt>(desk: &Desk, prompt: &P) -> Result<Option<DeskRange>> {
+    if !prompt
+        .confirm(
+            "Find out how low and high this desk can go? It will move all the way down and then \
+            all the way up. Make sure nothing is underneath or above it.",
+        )
+        .await
+    {
+        return Ok(None);
+    }
+
+    let start = desk.get_height().await?;
+    let range = match end_stops(desk, prompt).await {
+        Ok(range) => range,
+        Err(e) => {
+            // Don't leave the desk at an end stop, unless another command took over
+            if !matches!(e.downcast_ref::<DeskError>(), Some(DeskError::Interrupted)) {
+                let _ = desk.stop().await;
+                prompt.say("Returning to the starting height...");
+                let _ = desk.move_to_height(start).await;
+            }
+            return Err(e);
+        }
+    };
+
+    prompt.say("Returning to the starting height...");
+    desk.move_to_height(start).await?;
+
+    Ok(Some(range))
+}
+
+/// Drive the desk to the bottom and then the top, returning where it stopped
+async fn end_stops<P: Prompt>(desk: &Desk, prompt: &P) -> Result<DeskRange> {
+    prompt.say("Moving to the lowest position...");
+    let min = desk.move_to_end_stop(MovementCommand::Down).await?;
+    prompt.say("Moving to the highest position...");
+    let max = desk.move_to_end_stop(MovementCommand::Up).await?;
+
+    if max.abs_diff(min) < MIN_TRAVEL || max < min {
+        return Err(anyhow!(
+            "The desk only moved between {} and {}; is something blocking it?",
+            min, max
+        ));
+    }
+    Ok(DeskRange { min, max })
+}
+
+/// Store the range and fit default presets into it
+///
+/// Returns the saved config and the profiles whose presets were adjusted.
+pub fn save(range: DeskRange) -> Result<(Config, Vec<String>)> {
+    let mut adjusted = Vec::new();
+    let config = Config::try_update(|config| {
+        adjusted = config.set_desk_range(range);
+        config.validate()
+    })?;
+    Ok((config, adjusted))
+}
+
+/// Describe a discovered range and what changed because of it
+pub fn summary(range: &DeskRange, config: &Config, adjusted: &[String]) -> String {
+    let mut message = format!(
+        "The desk reaches from {} to {}; moves outside that range are refused.",
+        config.format_height(range.min),
+        config.format_height(range.max)
+    );
+    if !adjusted.is_empty() {
+        message.push_str(&format!(
+            " Default presets were fitted into it (profiles: {}).",
+            adjusted.join(", ")
+        ));
+    }
+    message
+}
@@ -0,0 +1,256 @@
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval, timeout};
+
+use crate::daemon::{DaemonClient, DeskService};
+use crate::desk::watch::WatchEvent;
+
+/// Tell systemd about our state, e.g. `READY=1` or `STATUS=...`
+///
+/// Does nothing unless we run in a unit with `Type=notify`.
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&path, state) {
+        log::debug!("Failed to notify systemd: {}", e);
+    }
+}
+
+fn send(path: &OsStr, state: &str) -> std::io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+
+    // Names starting with @ are in the abstract namespace
+    if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+        use std::os::linux::net::SocketAddrExt;
+        let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(state.as_bytes(), &address)?;
+        return Ok(());
+    }
+
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+/// How often systemd expects a watchdog ping, if the unit sets `WatchdogSec=`
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Line shown by `systemctl status` after a connection change
+fn status_text(event: &WatchEvent) -> Option<String> {
+    match event {
+        WatchEvent::Connecting { address } => Some(format!("Connecting to {}", address)),
+        WatchEvent::Connected { address } => Some(format!("Connected to {}", address)),
+        WatchEvent::Disconnected { error, retry_in_secs } => {
+            Some(format!("Disconnected ({}), retrying in {}s", error, retry_in_secs))
+        }
+        _ => None,
+    }
+}
+
+/// Report readiness and the connection state to systemd, and ping its
+/// watchdog for as long as the desk task responds
+///
+/// When the Bluetooth stack wedges the pings stop, and systemd restarts us.
+pub async fn supervise(service: Arc<DeskService>) {
+    if std::env::var_os("NOTIFY_SOCKET").is_none() {
+        return;
+    }
+
+    let (current, mut events) = service.subscribe();
+    let status = match current.iter().rev().find_map(status_text) {
+        Some(status) => status,
+        None if service.status().await.desk_address.is_none() => "No desk configured".to_string(),
+        None => "Starting".to_string(),
+    };
+    notify(&format!("READY=1\nSTATUS={}", status));
+
+    let period = watchdog_interval();
+    let mut ping = interval(period.unwrap_or(Duration::from_secs(3600)) / 2);
+    let mut responsive = true;
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if let Some(status) = status_text(&event) {
+                        notify(&format!("STATUS={}", status));
+                    }
+                }
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            },
+            _ = ping.tick(), if period.is_some() => {
+                let now_responsive = service.is_responsive().await;
+                if now_responsive {
+                    notify("WATCHDOG=1");
+                } else if responsive {
+                    log::error!("The desk connection is not responding; letting the systemd watchdog restart us");
+                }
+                responsive = now_responsive;
+            }
+        }
+    }
+}
+
+/// Like [`supervise`], for a process using a daemon running elsewhere:
+/// ready at once, with watchdog pings while that daemon answers
+pub async fn supervise_client(client: DaemonClient) {
+    if std::env::var_os("NOTIFY_SOCKET").is_none() {
+        return;
+    }
+    notify("READY=1\nSTATUS=Using the running desk-control daemon");
+
+    let Some(period) = watchdog_interval() else {
+        return;
+    };
+    let mut ping = interval(period / 2);
+    loop {
+        ping.tick().await;
+        if let Ok(Ok(_)) = timeout(period / 2, client.status()).await {
+            notify("WATCHDOG=1");
+        }
+    }
+}
+
+/// Name of the user unit for the daemon, or for the tray app
+pub fn unit_name(tray: bool) -> &'static str {
+    match tray {
+        true => "desk-control-tray.service",
+        false => "desk-control.service",
+    }
+}
+
+/// Where user units live: `~/.config/systemd/user`
+fn unit_dir() -> Result<PathBuf> {
+    let config = dirs::config_dir().context("Could not find the user config directory")?;
+    Ok(config.join("systemd").join("user"))
+}
+
+/// Contents of the unit starting `exe`
+fn unit_file(exe: &Path, tray: bool) -> String {
+    let exe = exec_arg(&exe.display().to_string());
+
+    let (description, command, after, wanted_by) = match tray {
+        true => (
+            "Desk Control tray app",
+            exe,
+            "After=graphical-session.target\nPartOf=graphical-session.target\n",
+            "graphical-session.target",
+        ),
+        false => ("Desk Control daemon", format!("{} daemon", exe), "", "default.target"),
+    };
+
+    format!(
+        "[Unit]\n\
+         Description={description}\n\
+         {after}\n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={command}\n\
+         Restart=on-failure\n\
+         RestartSec=5\n\
+         WatchdogSec=60\n\
+         TimeoutStopSec=10\n\
+         \n\
+         [Install]\n\
+         WantedBy={wanted_by}\n"
+    )
+}
+
+/// Quote an `ExecStart=` argument so systemd takes it literally
+///
+/// `%` starts a specifier and `$` a variable, even inside quotes.
+fn exec_arg(arg: &str) -> String {
+    let arg = arg.replace('%', "%%").replace('$', "$$");
+    if !arg.contains(|c: char| c.is_whitespace() || "\"'\\;".contains(c)) {
+        return arg;
+    }
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Run `systemctl --user` with `args`, returning whether it succeeded
+fn systemctl(args: &[&str]) -> bool {
+    std::process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Write the user unit for this executable, returning its path
+pub fn install(tray: bool) -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Could not find the desk-control executable")?;
+    let dir = unit_dir()?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+    let path = dir.join(unit_name(tray));
+    std::fs::write(&path, unit_file(&exe, tray))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// A unit removed by [`uninstall`]
+pub struct Uninstalled {
+    pub path: PathBuf,
+    /// Whether systemd stopped and disabled it; if not, its `WantedBy=` link may be left
+    pub disabled: bool,
+}
+
+/// Stop, disable and remove the user unit, if it is installed
+pub fn uninstall(tray: bool) -> Result<Option<Uninstalled>> {
+    let path = unit_dir()?.join(unit_name(tray));
+    if !path.exists() {
+        return Ok(None);
+    }
+    // Disabling first removes the link that `enable` made to the unit
+    let disabled = systemctl(&["disable", "--now", unit_name(tray)]);
+    std::fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+    systemctl(&["daemon-reload"]);
+    Ok(Some(Uninstalled { path, disabled }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_files() {
+        let daemon = unit_file(Path::new("/usr/bin/desk-control"), false);
+        assert!(daemon.contains("Type=notify\n"));
+        assert!(daemon.contains("ExecStart=/usr/bin/desk-control daemon\n"));
+        assert!(daemon.contains("WantedBy=default.target\n"));
+
+        let tray = unit_file(Path::new("/opt/Desk Control/desk-control"), true);
+        assert!(tray.contains("ExecStart=\"/opt/Desk Control/desk-control\"\n"));
+
+        assert_eq!(exec_arg("/home/me/100%/desk-control"), "/home/me/100%%/desk-control");
+        assert_eq!(exec_arg("/opt/a \"b\"/$x"), "\"/opt/a \\\"b\\\"/$$x\"");
+        assert!(tray.contains("PartOf=graphical-session.target\n"));
+    }
+
+    #[test]
+    fn test_notifies_the_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let socket = UnixDatagram::bind(&path).unwrap();
+
+        send(path.as_os_str(), "READY=1").unwrap();
+        let mut buffer = [0u8; 16];
+        let len = socket.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"READY=1");
+    }
+}
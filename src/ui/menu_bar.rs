@@ -67,13 +67,10 @@ impl MenuBarApp {
 
             // Add preset menu items
             for preset in DrinkSize::all() {
-                let height_mm = config.get_preset(preset);
-                let height_cm = height_mm as f32 / 10.0;
-
                 let label = format!(
-                    "{} - {:.1}cm",
+                    "{} - {}",
                     icons::get_text_icon(preset.name()),
-                    height_cm
+                    config.format_height(config.get_preset(preset))
                 );
 
                 let item = create_menu_item(
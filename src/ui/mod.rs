@@ -1,5 +1,6 @@
 pub mod icons;
 pub mod tray_app;
+pub mod tui;
 
 #[cfg(target_os = "macos")]
 pub mod menu_bar;
@@ -1,18 +1,28 @@
 use anyhow::Result;
-use std::sync::{Arc, Mutex};
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
 use tray_icon::{
-    menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu},
     TrayIcon, TrayIconBuilder,
 };
-use image::Rgba;
 
+use crate::autostart;
 use crate::config::{Config, DrinkSize};
+use crate::desk::Height;
+use crate::movement::{self, MoveRequest};
+use crate::units::DisplayUnit;
 
 /// Callback handler for menu item actions
 pub trait MenuCallback: Send + Sync {
-    fn on_preset_selected(&self, preset: DrinkSize);
+    fn on_move(&self, request: MoveRequest);
+    fn on_save_current_height(&self, preset: DrinkSize);
+    fn on_profile_selected(&self, name: &str);
     fn on_configure_desk(&self);
+    fn on_calibrate(&self);
     fn on_configure_presets(&self);
+    fn on_start_at_login(&self, enabled: bool) -> Result<()>;
+    /// Tell the user something went wrong
+    fn on_error(&self, message: &str);
     fn on_quit(&self);
 }
 
@@ -20,10 +30,18 @@ pub trait MenuCallback: Send + Sync {
 pub struct TrayApp {
     _tray_icon: TrayIcon,
     callback: Arc<dyn MenuCallback>,
+    display_unit: Cell<DisplayUnit>,
     current_height_item: MenuItem,
     preset_items: Vec<(DrinkSize, MenuItem)>,
+    nudge_up_item: MenuItem,
+    nudge_down_item: MenuItem,
+    save_preset_items: Vec<(DrinkSize, MenuItem)>,
+    profile_menu: Submenu,
+    profile_items: RefCell<Vec<(String, CheckMenuItem)>>,
     configure_desk_item: MenuItem,
+    calibrate_item: MenuItem,
     configure_presets_item: MenuItem,
+    start_at_login_item: CheckMenuItem,
     quit_item: MenuItem,
 }
 
@@ -38,7 +56,11 @@ impl TrayApp {
         menu.append(&title)?;
 
         // Add current height display (disabled item)
-        let current_height_item = MenuItem::new("Current: --cm", false, None);
+        let current_height_item = MenuItem::new(
+            format!("Current: --{}", config.display_unit.suffix()),
+            false,
+            None,
+        );
         menu.append(&current_height_item)?;
 
         menu.append(&PredefinedMenuItem::separator())?;
@@ -46,20 +68,34 @@ impl TrayApp {
         // Add preset menu items and store them
         let mut preset_items = Vec::new();
         for preset in DrinkSize::all() {
-            let height_mm = config.get_preset(preset);
-            let height_cm = height_mm as f32 / 10.0;
+            let item = MenuItem::new(preset_label(&config, preset), true, None);
+            menu.append(&item)?;
+            preset_items.push((preset, item));
+        }
 
-            let label = format!(
-                "{} {} - {:.1}cm",
-                get_icon_emoji(preset),
-                preset.name(),
-                height_cm
-            );
+        // Add small relative moves
+        let nudge_up_item = MenuItem::new(nudge_label("up", config.display_unit), true, None);
+        menu.append(&nudge_up_item)?;
+        let nudge_down_item = MenuItem::new(nudge_label("down", config.display_unit), true, None);
+        menu.append(&nudge_down_item)?;
 
+        // Add "save current height" submenu with one entry per preset
+        let save_preset_menu = Submenu::new("Save Current Height to...", true);
+        let mut save_preset_items = Vec::new();
+        for preset in DrinkSize::all() {
+            let label = format!("{} {}", get_icon_emoji(preset), preset.name());
             let item = MenuItem::new(label, true, None);
-            menu.append(&item)?;
-            preset_items.push((preset, item));
+            save_preset_menu.append(&item)?;
+            save_preset_items.push((preset, item));
         }
+        menu.append(&save_preset_menu)?;
+
+        menu.append(&PredefinedMenuItem::separator())?;
+
+        // Add profile switcher submenu
+        let profile_menu = Submenu::new(profile_menu_label(&config), true);
+        let profile_items = create_profile_items(&profile_menu, &config)?;
+        menu.append(&profile_menu)?;
 
         menu.append(&PredefinedMenuItem::separator())?;
 
@@ -67,11 +103,17 @@ impl TrayApp {
         let configure_desk_item = MenuItem::new("Configure Desk...", true, None);
         menu.append(&configure_desk_item)?;
 
+        let calibrate_item = MenuItem::new("Calibrate...", true, None);
+        menu.append(&calibrate_item)?;
+
         let configure_presets_item = MenuItem::new("Configure Presets...", true, None);
         menu.append(&configure_presets_item)?;
 
         menu.append(&PredefinedMenuItem::separator())?;
 
+        let start_at_login_item = CheckMenuItem::new("Start at login", true, autostart::is_enabled(), None);
+        menu.append(&start_at_login_item)?;
+
         // Add quit item
         let quit_item = MenuItem::new("Quit", true, None);
         menu.append(&quit_item)?;
@@ -87,20 +129,51 @@ impl TrayApp {
         Ok(Self {
             _tray_icon: tray_icon,
             callback,
+            display_unit: Cell::new(config.display_unit),
             current_height_item,
             preset_items,
+            nudge_up_item,
+            nudge_down_item,
+            save_preset_items,
+            profile_menu,
+            profile_items: RefCell::new(profile_items),
             configure_desk_item,
+            calibrate_item,
             configure_presets_item,
+            start_at_login_item,
             quit_item,
         })
     }
 
     /// Update the current height display in the menu
-    pub fn update_current_height(&self, height_cm: f32) {
-        let text = format!("Current: {:.1}cm", height_cm);
+    pub fn update_current_height(&self, height: Height) {
+        let text = format!("Current: {}", self.display_unit.get().format(height));
         self.current_height_item.set_text(text);
     }
 
+    /// Refresh preset and nudge labels and the profile switcher after a config change
+    pub fn refresh(&self, config: &Config) {
+        self.display_unit.set(config.display_unit);
+
+        for (preset, item) in &self.preset_items {
+            item.set_text(preset_label(config, *preset));
+        }
+        self.nudge_up_item.set_text(nudge_label("up", config.display_unit));
+        self.nudge_down_item.set_text(nudge_label("down", config.display_unit));
+
+        self.profile_menu.set_text(profile_menu_label(config));
+        self.start_at_login_item.set_checked(autostart::is_enabled());
+
+        let mut profile_items = self.profile_items.borrow_mut();
+        for (_, item) in profile_items.drain(..) {
+            let _ = self.profile_menu.remove(&item);
+        }
+        match create_profile_items(&self.profile_menu, config) {
+            Ok(items) => *profile_items = items,
+            Err(e) => log::error!("Failed to rebuild profile menu: {}", e),
+        }
+    }
+
     /// Process menu events (call this in your event loop)
     pub fn process_events(&self) {
         let menu_rx = MenuEvent::receiver();
@@ -109,15 +182,46 @@ impl TrayApp {
 
             // Check if it's a preset item
             if let Some((preset, _)) = self.preset_items.iter().find(|(_, item)| item_id == item.id()) {
-                self.callback.on_preset_selected(*preset);
+                self.callback.on_move(MoveRequest::Preset(*preset));
+                continue;
+            }
+
+            if let Some((preset, _)) = self.save_preset_items.iter().find(|(_, item)| item_id == item.id()) {
+                self.callback.on_save_current_height(*preset);
+                continue;
+            }
+
+            // Check if it's a profile item
+            let profile = self
+                .profile_items
+                .borrow()
+                .iter()
+                .find(|(_, item)| item_id == item.id())
+                .map(|(name, _)| name.clone());
+            if let Some(name) = profile {
+                self.callback.on_profile_selected(&name);
                 continue;
             }
 
             // Check other items
-            if item_id == self.configure_desk_item.id() {
+            if item_id == self.nudge_up_item.id() {
+                self.callback.on_move(MoveRequest::nudge_up(self.display_unit.get()));
+            } else if item_id == self.nudge_down_item.id() {
+                self.callback.on_move(MoveRequest::nudge_down(self.display_unit.get()));
+            } else if item_id == self.configure_desk_item.id() {
                 self.callback.on_configure_desk();
+            } else if item_id == self.calibrate_item.id() {
+                self.callback.on_calibrate();
             } else if item_id == self.configure_presets_item.id() {
                 self.callback.on_configure_presets();
+            } else if item_id == self.start_at_login_item.id() {
+                let result = self.callback.on_start_at_login(self.start_at_login_item.is_checked());
+                // Show what is on disk, whether or not that worked, before the error
+                self.start_at_login_item.set_checked(autostart::is_enabled());
+                if let Err(e) = result {
+                    log::error!("Failed to change autostart: {:#}", e);
+                    self.callback.on_error(&format!("Failed to change Start at login: {}", e));
+                }
             } else if item_id == self.quit_item.id() {
                 self.callback.on_quit();
             }
@@ -125,6 +229,40 @@ impl TrayApp {
     }
 }
 
+/// Label for a preset menu item, e.g. "🍺 Grande - 105.0cm"
+fn preset_label(config: &Config, preset: DrinkSize) -> String {
+    format!(
+        "{} {} - {}",
+        get_icon_emoji(preset),
+        preset.name(),
+        config.format_height(config.get_preset(preset))
+    )
+}
+
+/// Label for a nudge menu item, e.g. "Nudge up 1.0cm"
+fn nudge_label(direction: &str, unit: DisplayUnit) -> String {
+    format!("Nudge {} {}", direction, unit.format(movement::nudge_step(unit)))
+}
+
+fn profile_menu_label(config: &Config) -> String {
+    format!("Profile: {}", config.active_profile_name())
+}
+
+/// Add one check item per profile to `submenu`, checking the active one
+fn create_profile_items(
+    submenu: &Submenu,
+    config: &Config,
+) -> Result<Vec<(String, CheckMenuItem)>> {
+    let mut items = Vec::new();
+    for name in config.profile_names() {
+        let checked = name == config.active_profile_name();
+        let item = CheckMenuItem::new(&name, true, checked, None);
+        submenu.append(&item)?;
+        items.push((name, item));
+    }
+    Ok(items)
+}
+
 /// Create a simple tray icon (chair emoji as icon)
 fn create_tray_icon() -> tray_icon::Icon {
     // Create a simple 32x32 icon with a chair emoji
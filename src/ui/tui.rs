@@ -0,0 +1,632 @@
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Gauge, List, ListItem, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+
+use crate::config::{Config, DrinkSize};
+use crate::daemon::{self, DaemonClient};
+use crate::desk::error::user_message_for;
+use crate::desk::watch::WatchEvent;
+use crate::desk::{Height, MovementCommand};
+use crate::metrics::MoveSource;
+use crate::movement::{self, MoveRequest};
+use crate::ui::icons::get_text_icon;
+use crate::units::parse_height;
+
+/// How often the screen is redrawn while no key is pressed
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a hold keeps the desk moving after the last key event
+///
+/// Terminals only report key presses, not releases, so holding a key is
+/// seen as its autorepeat. This has to outlast the usual initial repeat
+/// delay (around 500ms) so the desk doesn't stutter before repeats start.
+const HOLD_GRACE: Duration = Duration::from_millis(600);
+
+/// The desk only keeps moving while commands keep arriving
+const HOLD_REPEAT: Duration = Duration::from_millis(300);
+
+/// What the screen asks the desk task to do
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    MoveTo(Height),
+    /// Keep moving up or down; repeated while the key is held
+    Hold(MovementCommand),
+    Stop,
+}
+
+/// What the desk task reports back to the screen
+#[derive(Debug)]
+enum Update {
+    Desk(WatchEvent),
+    Reached(Height),
+    Error(String),
+}
+
+/// Run the full-screen terminal UI until the user quits
+///
+/// Uses the same configuration, profiles and presets as the tray; preset
+/// changes are written to the config file so a running tray picks them up.
+/// The desk is reached through the daemon, which is started in this process
+/// if none is running.
+pub fn run(config: Config) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let client = runtime
+        .block_on(daemon::connect_or_start(config.clone()))?
+        .with_source(MoveSource::Tui);
+
+    let (actions, action_rx) = mpsc::unbounded_channel();
+    let (update_tx, updates) = mpsc::unbounded_channel();
+    let worker = runtime.spawn(run_desk(client, action_rx, update_tx));
+
+    let mut terminal = ratatui::try_init()?;
+    let result = App::new(config, actions).run(&mut terminal, updates);
+    ratatui::restore();
+
+    // The closed channel tells the desk task to stop our movement and return
+    runtime.block_on(async {
+        let _ = tokio::time::timeout(Duration::from_secs(3), worker).await;
+    });
+    result
+}
+
+/// Report the daemon's desk events and carry out actions
+///
+/// Returns once the screen drops its end of `updates`, or the daemon goes away.
+async fn run_desk(client: DaemonClient, mut actions: UnboundedReceiver<Action>, updates: UnboundedSender<Update>) {
+    let (events, mut received) = mpsc::unbounded_channel();
+    let subscriber = client.clone();
+    let subscription = tokio::spawn(async move { subscriber.subscribe(events).await });
+    let mut motion = Motion::new(client.clone(), updates.clone());
+
+    loop {
+        tokio::select! {
+            event = received.recv() => match event {
+                Some(event) => {
+                    let _ = updates.send(Update::Desk(event));
+                }
+                None => {
+                    let error = match subscription.await {
+                        Ok(Err(e)) => e.to_string(),
+                        _ => "The desk-control daemon stopped".to_string(),
+                    };
+                    let _ = updates.send(Update::Error(error));
+                    return;
+                }
+            },
+            action = actions.recv() => match action {
+                Some(Action::MoveTo(target)) => motion.move_to(target),
+                Some(Action::Hold(direction)) => motion.hold(direction),
+                Some(Action::Stop) => motion.stop(),
+                None => break,
+            },
+            _ = updates.closed() => break,
+        }
+    }
+
+    // Leave the desk still, unless someone else moved it meanwhile
+    if motion.cancel() {
+        if let Err(e) = client.stop().await {
+            log::debug!("Failed to stop the desk on exit: {}", e);
+        }
+    }
+}
+
+/// The movement currently in progress, so a new one can replace it
+struct Motion {
+    desk: DaemonClient,
+    updates: UnboundedSender<Update>,
+    task: Option<JoinHandle<()>>,
+    /// Direction and deadline of a running hold
+    hold: Option<(MovementCommand, Arc<Mutex<Instant>>)>,
+}
+
+impl Motion {
+    fn new(desk: DaemonClient, updates: UnboundedSender<Update>) -> Self {
+        Self {
+            desk,
+            updates,
+            task: None,
+            hold: None,
+        }
+    }
+
+    fn move_to(&mut self, target: Height) {
+        self.cancel();
+        let desk = self.desk.clone();
+        let updates = self.updates.clone();
+        self.task = Some(tokio::spawn(async move {
+            let _ = match desk.move_to_height(target).await {
+                Ok(()) => updates.send(Update::Reached(target)),
+                Err(e) => updates.send(Update::Error(e.user_message())),
+            };
+        }));
+    }
+
+    /// Start moving in `direction`, or keep an ongoing hold going a bit longer
+    fn hold(&mut self, direction: MovementCommand) {
+        let until = Instant::now() + HOLD_GRACE;
+
+        let running = self.task.as_ref().is_some_and(|task| !task.is_finished());
+        if let Some((held, deadline)) = &self.hold {
+            if running && *held == direction {
+                *deadline.lock().unwrap() = until;
+                return;
+            }
+        }
+
+        self.cancel();
+        let deadline = Arc::new(Mutex::new(until));
+        self.hold = Some((direction, Arc::clone(&deadline)));
+
+        let desk = self.desk.clone();
+        let updates = self.updates.clone();
+        self.task = Some(tokio::spawn(async move {
+            let result = async {
+                while Instant::now() < *deadline.lock().unwrap() {
+                    desk.hold(direction).await?;
+                    sleep(HOLD_REPEAT).await;
+                }
+                desk.stop().await
+            };
+            if let Err(e) = result.await {
+                let _ = updates.send(Update::Error(e.user_message()));
+            }
+        }));
+    }
+
+    fn stop(&mut self) {
+        self.cancel();
+        let desk = self.desk.clone();
+        let updates = self.updates.clone();
+        self.task = Some(tokio::spawn(async move {
+            if let Err(e) = desk.stop().await {
+                let _ = updates.send(Update::Error(e.user_message()));
+            }
+        }));
+    }
+
+    /// Abandon the movement in progress; returns whether one was running
+    fn cancel(&mut self) -> bool {
+        self.hold = None;
+        match self.task.take() {
+            Some(task) => {
+                let running = !task.is_finished();
+                task.abort();
+                running
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Motion {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// What the keyboard is currently used for
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mode {
+    Normal,
+    /// Waiting for 1-4 to pick the preset to edit
+    PickEdit,
+    /// Waiting for 1-4 to pick the preset to store the current height in
+    PickSave,
+    /// Typing a new height for a preset
+    Edit { preset: DrinkSize, input: String },
+}
+
+/// State of the screen
+struct App {
+    config: Config,
+    actions: UnboundedSender<Action>,
+    link: Option<WatchEvent>,
+    height: Option<Height>,
+    speed: i16,
+    /// Last result or error, shown in the footer
+    message: Option<(String, bool)>,
+    mode: Mode,
+    quit: bool,
+}
+
+impl App {
+    fn new(config: Config, actions: UnboundedSender<Action>) -> Self {
+        Self {
+            config,
+            actions,
+            link: None,
+            height: None,
+            speed: 0,
+            message: None,
+            mode: Mode::Normal,
+            quit: false,
+        }
+    }
+
+    fn run(mut self, terminal: &mut DefaultTerminal, mut updates: UnboundedReceiver<Update>) -> Result<()> {
+        // A daemon hosted here stopping (SIGTERM) ends the UI too
+        while !self.quit && !daemon::hosted_daemon_stopped() {
+            while let Ok(update) = updates.try_recv() {
+                self.apply(update);
+            }
+
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(FRAME_INTERVAL)? {
+                if let Event::Key(key) = event::read()? {
+                    // Some platforms report releases too; holds rely on repeats
+                    if key.kind != KeyEventKind::Release {
+                        self.on_key(key);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Desk(WatchEvent::Height { height_mm, speed, .. }) => {
+                self.height = Some(height_mm);
+                self.speed = speed;
+            }
+            // Our own moves are reported through `Reached` and `Error`
+            Update::Desk(
+                WatchEvent::MoveStarted { .. } | WatchEvent::MoveFinished { .. } | WatchEvent::MoveFailed { .. },
+            ) => {}
+            Update::Desk(event) => {
+                if !matches!(event, WatchEvent::Connected { .. }) {
+                    self.height = None;
+                    self.speed = 0;
+                }
+                self.link = Some(event);
+            }
+            Update::Reached(height) => {
+                self.info(format!("Reached {}", self.config.format_height(height)));
+            }
+            Update::Error(message) => self.error(message),
+        }
+    }
+
+    fn is_connected(&self) -> bool {
+        matches!(self.link, Some(WatchEvent::Connected { .. }))
+    }
+
+    fn info(&mut self, message: impl Into<String>) {
+        self.message = Some((message.into(), false));
+    }
+
+    fn error(&mut self, message: impl Into<String>) {
+        self.message = Some((message.into(), true));
+    }
+
+    /// Hand an action to the desk task, if there is a desk to act on
+    fn send(&mut self, action: Action) {
+        if !self.is_connected() {
+            self.error("Not connected to the desk");
+            return;
+        }
+        let _ = self.actions.send(action);
+    }
+
+    fn on_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        match self.mode.clone() {
+            Mode::Normal => self.on_normal_key(key.code),
+            Mode::PickEdit | Mode::PickSave => {
+                self.mode = match (preset_for_key(key.code), &self.mode) {
+                    (Some(preset), Mode::PickEdit) => Mode::Edit { preset, input: String::new() },
+                    (Some(preset), _) => {
+                        self.save_current(preset);
+                        Mode::Normal
+                    }
+                    (None, _) => Mode::Normal,
+                };
+            }
+            Mode::Edit { preset, mut input } => match key.code {
+                KeyCode::Enter => {
+                    self.mode = Mode::Normal;
+                    self.set_preset(preset, &input);
+                }
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Backspace => {
+                    input.pop();
+                    self.mode = Mode::Edit { preset, input };
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    self.mode = Mode::Edit { preset, input };
+                }
+                _ => {}
+            },
+        }
+    }
+
+    fn on_normal_key(&mut self, code: KeyCode) {
+        if let Some(preset) = preset_for_key(code) {
+            self.request(MoveRequest::Preset(preset));
+            return;
+        }
+
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Up | KeyCode::Char('k') => self.send(Action::Hold(MovementCommand::Up)),
+            KeyCode::Down | KeyCode::Char('j') => self.send(Action::Hold(MovementCommand::Down)),
+            KeyCode::Char('+') => self.request(MoveRequest::nudge_up(self.config.display_unit)),
+            KeyCode::Char('-') => self.request(MoveRequest::nudge_down(self.config.display_unit)),
+            KeyCode::Char(' ') | KeyCode::Char('s') => self.send(Action::Stop),
+            KeyCode::Char('e') => self.mode = Mode::PickEdit,
+            KeyCode::Char('c') => self.mode = Mode::PickSave,
+            _ => {}
+        }
+    }
+
+    /// Resolve a move against the active profile and send it to the desk
+    fn request(&mut self, request: MoveRequest) {
+        let Some(current) = self.height else {
+            return self.error("Not connected to the desk");
+        };
+        match request.resolve(&self.config, current) {
+            Ok(target) => {
+                self.info(format!("Moving to {}", self.config.format_height(target)));
+                self.send(Action::MoveTo(target));
+            }
+            Err(e) => self.error(e.user_message()),
+        }
+    }
+
+    fn save_current(&mut self, preset: DrinkSize) {
+        let Some(height) = self.height else {
+            return self.error("Not connected to the desk");
+        };
+        self.store_preset(preset, height);
+    }
+
+    fn set_preset(&mut self, preset: DrinkSize, input: &str) {
+        match parse_height(input, self.config.display_unit) {
+            Ok(height) => self.store_preset(preset, height),
+            Err(e) => self.error(e.to_string()),
+        }
+    }
+
+    fn store_preset(&mut self, preset: DrinkSize, height: Height) {
+        let result = self
+            .config
+            .move_limits()
+            .check(height)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| self.config.save_preset(preset, height));
+
+        match result {
+            Ok(config) => {
+                self.config = config;
+                self.info(format!(
+                    "Saved {} as {} preset",
+                    self.config.format_height(height),
+                    preset.name()
+                ));
+            }
+            Err(e) => self.error(user_message_for(&e)),
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [status, gauge, presets, footer] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Length(3),
+            Constraint::Min(6),
+            Constraint::Length(3),
+        ])
+        .areas(frame.area());
+
+        self.draw_status(frame, status);
+        self.draw_gauge(frame, gauge);
+        self.draw_presets(frame, presets);
+        self.draw_footer(frame, footer);
+    }
+
+    fn draw_status(&self, frame: &mut Frame, area: Rect) {
+        let height = match self.height {
+            Some(height) => self.config.format_height(height),
+            None => "--".to_string(),
+        };
+        let motion = match self.speed.signum() {
+            1 => "  ▲ moving up",
+            -1 => "  ▼ moving down",
+            _ => "",
+        };
+
+        let (link, color) = match &self.link {
+            None => ("Starting...".to_string(), Color::Yellow),
+            Some(WatchEvent::Connecting { address }) => {
+                (format!("Connecting to {}...", address), Color::Yellow)
+            }
+            Some(WatchEvent::Connected { address }) => (format!("Connected to {}", address), Color::Green),
+            Some(WatchEvent::Disconnected { error, retry_in_secs }) => (
+                format!("Disconnected ({}), retrying in {}s", error, retry_in_secs),
+                Color::Red,
+            ),
+            Some(_) => unreachable!("only connection events are stored as link state"),
+        };
+
+        let lines = vec![
+            Line::from(vec![
+                Span::styled(height, Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(motion),
+            ]),
+            Line::from(vec![
+                Span::styled("● ", Style::default().fg(color)),
+                Span::raw(link),
+                Span::raw(format!("   Profile: {}", self.config.active_profile_name())),
+            ]),
+        ];
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Desk ")), area);
+    }
+
+    fn draw_gauge(&self, frame: &mut Frame, area: Rect) {
+        let Some(height) = self.height else {
+            frame.render_widget(Block::bordered().title(" Height "), area);
+            return;
+        };
+
+        let (low, high) = self.gauge_range(height);
+        let span = f64::from(high.units().saturating_sub(low.units()).max(1));
+        let ratio = f64::from(height.units().saturating_sub(low.units())) / span;
+
+        let gauge = Gauge::default()
+            .block(Block::bordered().title(" Height "))
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio(ratio.clamp(0.0, 1.0))
+            .label(format!(
+                "{}  ({} – {})",
+                self.config.format_height(height),
+                self.config.format_height(low),
+                self.config.format_height(high)
+            ));
+        frame.render_widget(gauge, area);
+    }
+
+    /// The limits for moves, or else the span of the presets and current height
+    fn gauge_range(&self, current: Height) -> (Height, Height) {
+        let limits = self.config.move_limits();
+        let heights: Vec<Height> = DrinkSize::all()
+            .into_iter()
+            .map(|preset| self.config.get_preset(preset))
+            .chain(Some(current))
+            .collect();
+
+        let low = limits.min.unwrap_or_else(|| heights.iter().copied().min().unwrap_or(current));
+        let high = limits.max.unwrap_or_else(|| heights.iter().copied().max().unwrap_or(current));
+        (low, high)
+    }
+
+    fn draw_presets(&self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = DrinkSize::all()
+            .into_iter()
+            .enumerate()
+            .map(|(index, preset)| {
+                let height = self.config.get_preset(preset);
+                let here = self.height.is_some_and(|current| current.abs_diff(height).units() < 50);
+
+                let mut spans = vec![
+                    Span::styled(format!(" {} ", index + 1), Style::default().fg(Color::Yellow)),
+                    Span::raw(format!(" {:<12}", get_text_icon(preset.name()))),
+                    Span::raw(format!("{:>12}", self.config.format_height(height))),
+                ];
+                if here {
+                    spans.push(Span::styled("  ◀", Style::default().fg(Color::Green)));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+
+        frame.render_widget(List::new(items).block(Block::bordered().title(" Presets ")), area);
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let line = match (&self.mode, &self.message) {
+            (Mode::PickEdit, _) => Line::from("Edit which preset? 1-4, Esc to cancel"),
+            (Mode::PickSave, _) => Line::from("Save the current height as which preset? 1-4, Esc to cancel"),
+            (Mode::Edit { preset, input }, _) => Line::from(vec![
+                Span::raw(format!(
+                    "New height for {} (now {}): ",
+                    preset.name(),
+                    self.config.format_height(self.config.get_preset(*preset))
+                )),
+                Span::styled(format!("{}_", input), Style::default().add_modifier(Modifier::BOLD)),
+            ]),
+            (Mode::Normal, Some((message, is_error))) => {
+                let color = if *is_error { Color::Red } else { Color::Green };
+                Line::from(Span::styled(message.clone(), Style::default().fg(color)))
+            }
+            (Mode::Normal, None) => Line::from(""),
+        };
+
+        let unit = self.config.display_unit;
+        let help = format!(
+            " 1-4 preset · ↑↓ hold to move · +/- {} · space stop · e edit · c save current · q quit ",
+            unit.format(movement::nudge_step(unit))
+        );
+        frame.render_widget(Paragraph::new(line).block(Block::bordered().title(help)), area);
+    }
+}
+
+/// The preset selected by keys 1-4
+fn preset_for_key(code: KeyCode) -> Option<DrinkSize> {
+    match code {
+        KeyCode::Char(c) => {
+            let index = c.to_digit(10)?.checked_sub(1)?;
+            DrinkSize::all().get(index as usize).copied()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_keys() {
+        assert_eq!(preset_for_key(KeyCode::Char('1')), Some(DrinkSize::Short));
+        assert_eq!(preset_for_key(KeyCode::Char('4')), Some(DrinkSize::Venti));
+        assert_eq!(preset_for_key(KeyCode::Char('0')), None);
+        assert_eq!(preset_for_key(KeyCode::Char('5')), None);
+        assert_eq!(preset_for_key(KeyCode::Up), None);
+    }
+
+    #[test]
+    fn test_edit_mode_saves_nothing_on_escape() {
+        let (actions, _) = mpsc::unbounded_channel();
+        let mut app = App::new(Config::default(), actions);
+
+        app.on_key(KeyEvent::from(KeyCode::Char('e')));
+        app.on_key(KeyEvent::from(KeyCode::Char('2')));
+        app.on_key(KeyEvent::from(KeyCode::Char('9')));
+        assert_eq!(
+            app.mode,
+            Mode::Edit {
+                preset: DrinkSize::Tall,
+                input: "9".to_string()
+            }
+        );
+
+        app.on_key(KeyEvent::from(KeyCode::Esc));
+        assert_eq!(app.mode, Mode::Normal);
+        assert!(app.message.is_none());
+    }
+
+    #[test]
+    fn test_moves_need_a_connection() {
+        let (actions, mut sent) = mpsc::unbounded_channel();
+        let mut app = App::new(Config::default(), actions);
+
+        app.on_key(KeyEvent::from(KeyCode::Char('1')));
+        assert!(sent.try_recv().is_err());
+        assert!(matches!(app.message, Some((_, true))));
+
+        app.apply(Update::Desk(WatchEvent::Connected { address: "AA".to_string() }));
+        app.apply(Update::Desk(WatchEvent::Height {
+            height_mm: Height::from_units(10000),
+            speed: 0,
+            moving: false,
+        }));
+        app.on_key(KeyEvent::from(KeyCode::Char('1')));
+        let expected = Config::default().get_preset(DrinkSize::Short);
+        assert!(matches!(sent.try_recv(), Ok(Action::MoveTo(height)) if height == expected));
+    }
+}
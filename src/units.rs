@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::desk::Height;
+
+const MM_PER_INCH: f64 = 25.4;
+
+/// Unit used when showing heights to the user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayUnit {
+    /// Centimeters with one decimal (105.0cm)
+    #[default]
+    Cm,
+    /// Millimeters, with a decimal only when needed (1050mm, 1050.5mm)
+    Mm,
+    /// Inches rounded to the nearest 1/8 (41 3/8in)
+    #[serde(alias = "inches", alias = "inch")]
+    In,
+}
+
+impl DisplayUnit {
+    /// Unit suffix as used in input and output ("cm", "mm", "in")
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::Cm => "cm",
+            Self::Mm => "mm",
+            Self::In => "in",
+        }
+    }
+
+    /// Format a height in this unit
+    pub fn format(&self, height: Height) -> String {
+        match (self, height.units() % 10) {
+            (Self::Cm, _) => format!("{:.1}cm", height.as_mm() / 10.0),
+            (Self::Mm, 0) => format!("{}mm", height.as_mm()),
+            (Self::Mm, _) => format!("{:.1}mm", height.as_mm()),
+            (Self::In, _) => format_inches(height),
+        }
+    }
+
+    /// Convert a value expressed in this unit to millimeters
+    fn to_mm(self, value: f64) -> f64 {
+        match self {
+            Self::Cm => value * 10.0,
+            Self::Mm => value,
+            Self::In => value * MM_PER_INCH,
+        }
+    }
+}
+
+impl fmt::Display for DisplayUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.suffix())
+    }
+}
+
+impl FromStr for DisplayUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "cm" | "centimeter" | "centimeters" => Ok(Self::Cm),
+            "mm" | "millimeter" | "millimeters" => Ok(Self::Mm),
+            "in" | "inch" | "inches" | "\"" => Ok(Self::In),
+            other => Err(anyhow!("Unknown unit '{}' (expected cm, mm or in)", other)),
+        }
+    }
+}
+
+/// Format a height as inches with an eighth-inch fraction, e.g. "41 3/8in"
+fn format_inches(height: Height) -> String {
+    let eighths = (height.as_mm() / MM_PER_INCH * 8.0).round() as u64;
+    let whole = eighths / 8;
+    let numerator = eighths % 8;
+
+    if numerator == 0 {
+        return format!("{}in", whole);
+    }
+
+    // Reduce to lowest terms (4/8 -> 1/2)
+    let shift = numerator.trailing_zeros();
+    let (numerator, denominator) = (numerator >> shift, 8 >> shift);
+
+    if whole == 0 {
+        format!("{}/{}in", numerator, denominator)
+    } else {
+        format!("{} {}/{}in", whole, numerator, denominator)
+    }
+}
+
+/// Parse a user-entered height
+///
+/// Accepts an optional unit suffix ("105cm", "1050mm", "41.5in", "41 1/2in",
+/// "41.5\""). Bare numbers are interpreted in `default_unit`.
+pub fn parse_height(input: &str, default_unit: DisplayUnit) -> Result<Height> {
+    let trimmed = input.trim();
+    let split = trimmed
+        .find(|c: char| c.is_ascii_alphabetic() || c == '"')
+        .unwrap_or(trimmed.len());
+    let (number, suffix) = trimmed.split_at(split);
+
+    let unit = if suffix.trim().is_empty() {
+        default_unit
+    } else {
+        suffix.parse()?
+    };
+
+    let value = parse_number(number.trim())
+        .ok_or_else(|| anyhow!("Invalid height '{}'", input.trim()))?;
+    Height::from_mm_f64(unit.to_mm(value))
+        .filter(|height| height.units() > 0)
+        .ok_or_else(|| anyhow!("Height '{}' is out of range", input.trim()))
+}
+
+/// Parse a decimal or mixed fraction ("41.5", "41 1/2", "3/4")
+fn parse_number(s: &str) -> Option<f64> {
+    let (whole, fraction) = match s.rsplit_once(' ') {
+        Some((whole, fraction)) => (whole.trim().parse::<f64>().ok()?, fraction),
+        None if s.contains('/') => (0.0, s),
+        None => return s.parse::<f64>().ok().filter(|v| v.is_finite()),
+    };
+
+    let (numerator, denominator) = fraction.split_once('/')?;
+    let numerator: f64 = numerator.trim().parse().ok()?;
+    let denominator: f64 = denominator.trim().parse().ok()?;
+    if denominator == 0.0 {
+        return None;
+    }
+
+    Some(whole + numerator / denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mm(mm: u16) -> Height {
+        Height::from_mm(mm).unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(DisplayUnit::Cm.format(mm(1050)), "105.0cm");
+        assert_eq!(DisplayUnit::Mm.format(mm(1050)), "1050mm");
+        assert_eq!(DisplayUnit::Mm.format(Height::from_units(10505)), "1050.5mm");
+        assert_eq!(DisplayUnit::In.format(mm(1054)), "41 1/2in");
+        assert_eq!(DisplayUnit::In.format(mm(1016)), "40in");
+        assert_eq!(DisplayUnit::In.format(mm(10)), "3/8in");
+    }
+
+    #[test]
+    fn test_parse_with_suffix() {
+        assert_eq!(parse_height("105cm", DisplayUnit::Mm).unwrap(), mm(1050));
+        assert_eq!(parse_height("1050mm", DisplayUnit::Cm).unwrap(), mm(1050));
+        assert_eq!(parse_height("41.5in", DisplayUnit::Cm).unwrap().units(), 10541);
+        assert_eq!(parse_height("41 1/2 in", DisplayUnit::Cm).unwrap().units(), 10541);
+        assert_eq!(parse_height("41.5\"", DisplayUnit::Cm).unwrap().units(), 10541);
+        assert_eq!(parse_height("1050.5mm", DisplayUnit::Cm).unwrap().units(), 10505);
+        assert_eq!(parse_height(" 72.5 CM ", DisplayUnit::Mm).unwrap(), mm(725));
+    }
+
+    #[test]
+    fn test_parse_uses_default_unit() {
+        assert_eq!(parse_height("105", DisplayUnit::Cm).unwrap(), mm(1050));
+        assert_eq!(parse_height("1050", DisplayUnit::Mm).unwrap(), mm(1050));
+    }
+
+    #[test]
+    fn test_parse_rejects_garbage() {
+        assert!(parse_height("tall", DisplayUnit::Cm).is_err());
+        assert!(parse_height("105ft", DisplayUnit::Cm).is_err());
+        assert!(parse_height("-5cm", DisplayUnit::Cm).is_err());
+        assert!(parse_height("1/0in", DisplayUnit::Cm).is_err());
+        assert!(parse_height("99999cm", DisplayUnit::Cm).is_err());
+    }
+}